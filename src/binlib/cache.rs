@@ -0,0 +1,344 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+
+use serde::{Deserialize, Serialize};
+
+use tempfile::NamedTempFile;
+
+use crate::checksum::{sha256_bytes, sha256_file};
+use crate::source::Release;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// A uniquely named temporary sibling of a path, so that concurrent writers never share one.
+fn temp_sibling(path: &Path) -> Result<NamedTempFile> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow!("{} has no parent directory", path.display()))?;
+    Ok(NamedTempFile::new_in(dir)?)
+}
+
+/// Writes a file through a temporary sibling, so that readers never see it partially written.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp = temp_sibling(path)?;
+    tmp.write_all(contents)?;
+    tmp.persist(path)?;
+    Ok(())
+}
+
+/// A downloaded file kept in the cache.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CacheEntry {
+    pub url: String,
+    pub file_name: String,
+    pub sha256: String,
+    pub size: u64,
+
+    /// The revision of the release the file was downloaded for, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+
+    /// When the entry was last added or used, in seconds since the epoch.
+    pub last_used: u64,
+}
+
+impl CacheEntry {
+    /// Time elapsed since the entry was last added or used.
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.last_used))
+    }
+}
+
+/// What a prune removed from the cache.
+#[derive(Debug, Default)]
+pub struct PruneSummary {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Local store of downloads, and of the releases last listed for each repository.
+///
+/// Files are stored once under their SHA-256 digest in `blobs`, and indexed by the URL they were
+/// downloaded from in `entries`, along with the release revision. Partial downloads are kept in
+/// `partial` until they complete.
+#[derive(Clone, Debug)]
+pub struct Cache {
+    root: PathBuf,
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Cache {
+        Cache { root: root.into() }
+    }
+
+    fn dir(&self, name: &str) -> Result<PathBuf> {
+        let dir = self.root.join(name);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create cache directory {}", dir.display()))?;
+        Ok(dir)
+    }
+
+    fn entry_path(&self, url: &str) -> Result<PathBuf> {
+        Ok(self
+            .dir("entries")?
+            .join(format!("{}.json", sha256_bytes(url.as_bytes()))))
+    }
+
+    fn blob_path(&self, sha256: &str) -> Result<PathBuf> {
+        Ok(self.dir("blobs")?.join(sha256))
+    }
+
+    /// Where the download of a URL is kept until it completes.
+    pub fn partial_path(&self, url: &str) -> Result<PathBuf> {
+        Ok(self
+            .dir("partial")?
+            .join(format!("{}.part", sha256_bytes(url.as_bytes()))))
+    }
+
+    fn read_entry(path: &Path) -> Result<CacheEntry> {
+        let raw = fs::read(path)?;
+        Ok(serde_json::from_slice(&raw)?)
+    }
+
+    fn write_entry(&self, entry: &CacheEntry) -> Result<()> {
+        write_atomic(&self.entry_path(&entry.url)?, &serde_json::to_vec(entry)?)
+    }
+
+    /// The cached file with a given SHA-256 digest, wherever it was downloaded from.
+    ///
+    /// Files whose contents no longer match their digest are dropped.
+    pub fn lookup_digest(&self, sha256: &str) -> Result<Option<PathBuf>> {
+        let sha256 = sha256.to_lowercase();
+        let blob = self.blob_path(&sha256)?;
+        if !blob.exists() {
+            return Ok(None);
+        }
+        if sha256_file(&blob)? != sha256 {
+            tracing::warn!(sha256=%sha256, "dropping corrupted cached file");
+            fs::remove_file(&blob)?;
+            return Ok(None);
+        }
+        Ok(Some(blob))
+    }
+
+    /// The cached file downloaded from a URL for a given release revision, checked against its
+    /// recorded digest.
+    ///
+    /// Entries recorded for another revision are ignored, as a moving tag or a re-uploaded asset
+    /// keeps its URL. Entries whose file is missing or corrupted are dropped.
+    pub fn lookup(&self, url: &str, revision: &str) -> Result<Option<(PathBuf, CacheEntry)>> {
+        let entry_path = self.entry_path(url)?;
+        let mut entry = match Self::read_entry(&entry_path) {
+            Ok(entry) if entry.revision.as_deref() == Some(revision) => entry,
+            _ => return Ok(None),
+        };
+
+        let blob = self.blob_path(&entry.sha256)?;
+        if !blob.exists() || sha256_file(&blob)? != entry.sha256 {
+            tracing::warn!(url=%url, "dropping invalid cache entry");
+            // A corrupted file is of no use to the other entries referring to it either.
+            if blob.exists() {
+                fs::remove_file(&blob)?;
+            }
+            self.remove(url)?;
+            return Ok(None);
+        }
+
+        entry.last_used = now();
+        self.write_entry(&entry)?;
+        Ok(Some((blob, entry)))
+    }
+
+    /// Adds a file downloaded from a URL for a release revision to the cache.
+    pub fn insert(
+        &self,
+        url: &str,
+        file_name: &str,
+        path: &Path,
+        sha256: &str,
+        revision: Option<&str>,
+    ) -> Result<()> {
+        let blob = self.blob_path(sha256)?;
+        if !blob.exists() {
+            let mut tmp = temp_sibling(&blob)?;
+            io::copy(&mut File::open(path)?, &mut tmp)?;
+            tmp.persist(&blob)?;
+        }
+
+        self.write_entry(&CacheEntry {
+            url: String::from(url),
+            file_name: String::from(file_name),
+            sha256: String::from(sha256),
+            size: fs::metadata(&blob)?.len(),
+            revision: revision.map(String::from),
+            last_used: now(),
+        })?;
+        tracing::debug!(url=%url, sha256=%sha256, "cached download");
+        Ok(())
+    }
+
+    /// Adds a small document downloaded from a URL to the cache.
+    pub fn insert_bytes(
+        &self,
+        url: &str,
+        file_name: &str,
+        contents: &[u8],
+        revision: Option<&str>,
+    ) -> Result<()> {
+        let sha256 = sha256_bytes(contents);
+        let blob = self.blob_path(&sha256)?;
+        if !blob.exists() {
+            write_atomic(&blob, contents)?;
+        }
+        self.insert(url, file_name, &blob, &sha256, revision)
+    }
+
+    /// Forgets the file downloaded from a URL.
+    ///
+    /// Files are shared by the URLs they were downloaded from, so they are only deleted once
+    /// no entry refers to them, by [`Cache::prune`].
+    pub fn remove(&self, url: &str) -> Result<()> {
+        let entry_path = self.entry_path(url)?;
+        if entry_path.exists() {
+            fs::remove_file(entry_path)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the cached downloads.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(self.dir("entries")?)? {
+            let path = dir_entry?.path();
+            match Self::read_entry(&path) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => tracing::warn!(path=?path, error=%e, "skipping unreadable cache entry"),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Removes everything from the cache.
+    pub fn clean(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.root) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the downloads unused for longer than `max_age`, as well as partial downloads and
+    /// files no entry refers to.
+    pub fn prune(&self, max_age: Duration) -> Result<PruneSummary> {
+        let mut summary = PruneSummary::default();
+        let mut kept = HashSet::new();
+
+        for entry in self.entries()? {
+            if entry.age() > max_age {
+                fs::remove_file(self.entry_path(&entry.url)?)?;
+                summary.entries += 1;
+            } else {
+                kept.insert(entry.sha256);
+            }
+        }
+
+        for dir_entry in fs::read_dir(self.dir("blobs")?)? {
+            let dir_entry = dir_entry?;
+            if !kept.contains(dir_entry.file_name().to_string_lossy().as_ref()) {
+                summary.bytes += dir_entry.metadata()?.len();
+                fs::remove_file(dir_entry.path())?;
+            }
+        }
+
+        for dir_entry in fs::read_dir(self.dir("partial")?)? {
+            let dir_entry = dir_entry?;
+            let metadata = dir_entry.metadata()?;
+            let age = metadata.modified()?.elapsed().unwrap_or_default();
+            if age > max_age {
+                summary.bytes += metadata.len();
+                fs::remove_file(dir_entry.path())?;
+            }
+        }
+
+        Ok(summary)
+    }
+
+    fn releases_path(&self, repository: &str) -> Result<PathBuf> {
+        Ok(self
+            .dir("releases")?
+            .join(format!("{}.json", sha256_bytes(repository.as_bytes()))))
+    }
+
+    /// The releases last listed for a repository, most recent first.
+    pub fn releases(&self, repository: &str) -> Result<Vec<Release>> {
+        let raw = fs::read(self.releases_path(repository)?)
+            .map_err(|_| anyhow!("No release of {} is cached", repository))?;
+        Ok(serde_json::from_slice(&raw)?)
+    }
+
+    /// Records releases listed for a repository, on top of those listed before.
+    pub fn record_releases(&self, repository: &str, releases: &[Release]) -> Result<()> {
+        let mut merged = releases.to_vec();
+        for release in self.releases(repository).unwrap_or_default() {
+            if !merged.iter().any(|r| r.tag_name() == release.tag_name()) {
+                merged.push(release);
+            }
+        }
+        write_atomic(
+            &self.releases_path(repository)?,
+            &serde_json::to_vec(&merged)?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn remove_keeps_files_shared_with_other_urls() {
+        let root = tempdir().unwrap();
+        let cache = Cache::new(root.path());
+        cache
+            .insert_bytes("https://a/tool", "tool", b"contents", Some("r1"))
+            .unwrap();
+        cache
+            .insert_bytes("https://b/tool", "tool", b"contents", Some("r1"))
+            .unwrap();
+
+        cache.remove("https://a/tool").unwrap();
+
+        assert!(cache.lookup("https://a/tool", "r1").unwrap().is_none());
+        let (blob, _) = cache.lookup("https://b/tool", "r1").unwrap().unwrap();
+        assert_eq!(fs::read(blob).unwrap(), b"contents");
+    }
+
+    #[test]
+    fn prune_deletes_files_once_unreferenced() {
+        let root = tempdir().unwrap();
+        let cache = Cache::new(root.path());
+        cache
+            .insert_bytes("https://a/tool", "tool", b"contents", None)
+            .unwrap();
+        let sha256 = sha256_bytes(b"contents");
+
+        cache.prune(Duration::from_secs(3600)).unwrap();
+        assert!(cache.lookup_digest(&sha256).unwrap().is_some());
+
+        cache.remove("https://a/tool").unwrap();
+        let summary = cache.prune(Duration::from_secs(3600)).unwrap();
+        assert_eq!(summary.bytes, b"contents".len() as u64);
+        assert!(cache.lookup_digest(&sha256).unwrap().is_none());
+    }
+}
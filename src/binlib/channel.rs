@@ -0,0 +1,71 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+
+use serde::{Deserialize, Serialize};
+
+/// The kind of releases a package follows.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Channel {
+    /// Stable releases only.
+    #[default]
+    Stable,
+
+    /// Stable releases and prereleases, such as release candidates.
+    Prerelease,
+
+    /// A single release whose tag is moved to every new build, such as `nightly`.
+    Nightly(String),
+}
+
+impl Channel {
+    pub fn is_stable(&self) -> bool {
+        *self == Channel::Stable
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Stable => write!(f, "stable"),
+            Channel::Prerelease => write!(f, "prerelease"),
+            Channel::Nightly(tag) => write!(f, "nightly:{}", tag),
+        }
+    }
+}
+
+impl FromStr for Channel {
+    type Err = Error;
+
+    /// Parses `stable`, `prerelease` or `nightly:<tag>`.
+    fn from_str(raw: &str) -> Result<Channel> {
+        match raw {
+            "stable" => Ok(Channel::Stable),
+            "prerelease" | "pre" => Ok(Channel::Prerelease),
+            "nightly" => Ok(Channel::Nightly(String::from("nightly"))),
+            _ => match raw.strip_prefix("nightly:") {
+                Some(tag) if !tag.is_empty() => Ok(Channel::Nightly(String::from(tag))),
+                _ => Err(anyhow!(
+                    "Invalid channel \"{}\" - expected stable, prerelease or nightly:<tag>",
+                    raw
+                )),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Channel {
+    type Error = Error;
+
+    fn try_from(raw: String) -> Result<Channel> {
+        raw.parse()
+    }
+}
+
+impl From<Channel> for String {
+    fn from(channel: Channel) -> String {
+        channel.to_string()
+    }
+}
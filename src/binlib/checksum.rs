@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+
+use regex::Regex;
+
+use sha2::{Digest, Sha256};
+
+/// Computes the hex-encoded SHA-256 digest of a file.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Computes the hex-encoded SHA-256 digest of some bytes.
+pub fn sha256_bytes(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Whether an asset name designates a checksums file covering several assets,
+/// such as `checksums.txt` or `tool_1.2.3_SHA256SUMS`.
+pub fn is_aggregated_checksum_file(name: &str) -> bool {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN
+        .get_or_init(|| Regex::new(r"(?i)(^|[_.-])(sha256sums?|checksums?)(\.txt)?$").unwrap())
+        .is_match(name)
+}
+
+/// Whether an asset name designates the checksum of a single other asset, such as `tool.tar.gz.sha256`.
+pub fn is_sidecar_checksum_file(name: &str) -> bool {
+    name.to_lowercase().ends_with(".sha256")
+}
+
+/// Expected SHA-256 digests of release assets, keyed by file name.
+#[derive(Debug, Default)]
+pub struct Checksums {
+    digests: HashMap<String, String>,
+}
+
+impl Checksums {
+    /// Adds the digests listed in a checksums file.
+    ///
+    /// Both the GNU (`<hash>  [*]<name>`) and BSD (`SHA256 (<name>) = <hash>`) formats are
+    /// understood. A line holding only a hash is attributed to `default_name`.
+    pub fn parse(&mut self, contents: &str, default_name: Option<&str>) {
+        static BSD: OnceLock<Regex> = OnceLock::new();
+        static GNU: OnceLock<Regex> = OnceLock::new();
+        let bsd = BSD.get_or_init(|| Regex::new(r"^SHA256 \((.+)\) = ([0-9a-fA-F]{64})$").unwrap());
+        let gnu = GNU.get_or_init(|| Regex::new(r"^([0-9a-fA-F]{64})(?:\s+\*?(.+))?$").unwrap());
+
+        for line in contents.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (hash, name) = if let Some(caps) = bsd.captures(line) {
+                (caps[2].to_string(), Some(caps[1].to_string()))
+            } else if let Some(caps) = gnu.captures(line) {
+                (
+                    caps[1].to_string(),
+                    caps.get(2).map(|m| m.as_str().to_string()),
+                )
+            } else {
+                tracing::trace!(line=%line, "skipping unrecognized checksum line");
+                continue;
+            };
+
+            // Entries are sometimes listed with a leading `./` or a directory.
+            let name = name
+                .map(|n| String::from(n.trim().rsplit('/').next().unwrap_or_default()))
+                .or_else(|| default_name.map(String::from));
+            if let Some(name) = name {
+                self.digests.insert(name, hash.to_lowercase());
+            }
+        }
+    }
+
+    pub fn get(&self, file_name: &str) -> Option<&str> {
+        self.digests.get(file_name).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST_A: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+    const DIGEST_B: &str = "486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7";
+
+    fn parsed(contents: &str, default_name: Option<&str>) -> Checksums {
+        let mut checksums = Checksums::default();
+        checksums.parse(contents, default_name);
+        checksums
+    }
+
+    #[test]
+    fn parses_gnu_lines() {
+        let checksums = parsed(
+            &format!(
+                "{}  tool-linux-amd64.tar.gz\n{} ./dist/tool-darwin-arm64.tar.gz\n",
+                DIGEST_A, DIGEST_B
+            ),
+            None,
+        );
+        assert_eq!(checksums.get("tool-linux-amd64.tar.gz"), Some(DIGEST_A));
+        assert_eq!(checksums.get("tool-darwin-arm64.tar.gz"), Some(DIGEST_B));
+    }
+
+    #[test]
+    fn parses_binary_mode_lines() {
+        let checksums = parsed(&format!("{} *tool.exe", DIGEST_A), None);
+        assert_eq!(checksums.get("tool.exe"), Some(DIGEST_A));
+        assert_eq!(checksums.get("*tool.exe"), None);
+    }
+
+    #[test]
+    fn parses_bsd_lines() {
+        let checksums = parsed(
+            &format!("SHA256 (tool.tar.gz) = {}", DIGEST_A.to_uppercase()),
+            None,
+        );
+        assert_eq!(checksums.get("tool.tar.gz"), Some(DIGEST_A));
+    }
+
+    #[test]
+    fn attributes_bare_hashes_to_default_name() {
+        let checksums = parsed(&format!("{}\n", DIGEST_A), Some("tool.tar.gz"));
+        assert_eq!(checksums.get("tool.tar.gz"), Some(DIGEST_A));
+        assert!(parsed(DIGEST_A, None).digests.is_empty());
+    }
+
+    #[test]
+    fn skips_unrecognized_lines() {
+        let checksums = parsed(
+            &format!("# checksums\nmd5  abc  tool\n{}  tool.tar.gz", DIGEST_A),
+            None,
+        );
+        assert_eq!(checksums.digests.len(), 1);
+    }
+
+    #[test]
+    fn detects_mismatching_digest() {
+        let checksums = parsed(&format!("{}  tool.tar.gz", DIGEST_A), None);
+        assert_eq!(
+            checksums.get("tool.tar.gz"),
+            Some(sha256_bytes(b"hello").as_str())
+        );
+        assert_ne!(
+            checksums.get("tool.tar.gz"),
+            Some(sha256_bytes(b"hello!").as_str())
+        );
+    }
+
+    #[test]
+    fn detects_aggregated_checksum_files() {
+        for name in [
+            "checksums.txt",
+            "CHECKSUMS",
+            "SHA256SUMS",
+            "tool_1.2.3_SHA256SUMS",
+            "tool-1.2.3-checksums.txt",
+            "sha256sum.txt",
+        ] {
+            assert!(is_aggregated_checksum_file(name), "{}", name);
+        }
+        for name in [
+            "tool.tar.gz",
+            "tool.tar.gz.sha256",
+            "mychecksums.txt",
+            "checksums.txt.sig",
+        ] {
+            assert!(!is_aggregated_checksum_file(name), "{}", name);
+        }
+    }
+}
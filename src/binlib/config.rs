@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::io::BufWriter;
 
@@ -27,6 +29,58 @@ fn default_state_file_path() -> String {
     )
 }
 
+fn default_cache_dir() -> String {
+    String::from(
+        dirs::cache_dir()
+            .or_else(dirs::config_dir)
+            .unwrap()
+            .join("purposed")
+            .join("binman")
+            .to_str()
+            .unwrap(),
+    )
+}
+
+fn default_max_concurrent_downloads() -> usize {
+    4
+}
+
+fn env_token(vars: &[&str]) -> Option<String> {
+    vars.iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|token| !token.is_empty())
+}
+
+/// The API spoken by a code host.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiFlavor {
+    Github,
+    Gitlab,
+    Gitea,
+    Forgejo,
+}
+
+/// Per-host settings, keyed by host name in `hosts`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct HostConfig {
+    /// The API spoken by the host, guessed from the host name when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flavor: Option<ApiFlavor>,
+
+    /// Base URL of the API, derived from the host and flavor when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+
+    /// Token used to authenticate against the host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+
+    /// Path to a PEM-encoded CA certificate to trust when connecting to the host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<String>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct Config {
     #[serde(default = "default_code_host")]
@@ -37,6 +91,39 @@ pub struct Config {
 
     #[serde(default = "default_state_file_path")]
     pub state_file_path: String,
+
+    /// Directory holding downloads, including partial ones to resume.
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: String,
+
+    /// Most downloads running at once, across assets and packages.
+    #[serde(default = "default_max_concurrent_downloads")]
+    pub max_concurrent_downloads: usize,
+
+    /// Token used to authenticate against the GitHub API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_token: Option<String>,
+
+    /// Token used to authenticate against the GitLab API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gitlab_token: Option<String>,
+
+    /// Whether to wait for the GitHub rate limit to reset instead of failing.
+    #[serde(default)]
+    pub wait_on_rate_limit: bool,
+
+    /// Whether to ask GitHub-flavored hosts for their latest release rather than picking the highest
+    /// stable version among all releases.
+    #[serde(default)]
+    pub use_latest_endpoint: bool,
+
+    /// Whether to refuse installing assets for which the release publishes no checksum.
+    #[serde(default)]
+    pub require_checksums: bool,
+
+    /// Settings of self-hosted forges, keyed by host name.
+    #[serde(default)]
+    pub hosts: HashMap<String, HostConfig>,
 }
 
 impl Config {
@@ -64,9 +151,54 @@ impl Config {
         Ok(cfg)
     }
 
+    /// The API flavor spoken by a host, from its configuration or guessed from its name.
+    ///
+    /// Unknown hosts are assumed to be GitHub Enterprise instances.
+    pub fn flavor_for(&self, host: &str) -> ApiFlavor {
+        if let Some(flavor) = self.hosts.get(host).and_then(|h| h.flavor) {
+            return flavor;
+        }
+        if host.contains("gitlab") {
+            ApiFlavor::Gitlab
+        } else if host.contains("forgejo") {
+            ApiFlavor::Forgejo
+        } else if host.contains("gitea") || host == "codeberg.org" {
+            ApiFlavor::Gitea
+        } else {
+            ApiFlavor::Github
+        }
+    }
+
+    /// The token to use for a host.
+    ///
+    /// A token configured for the host takes precedence. The token environment variables and the
+    /// top-level tokens are only ever sent to github.com and gitlab.com.
+    pub fn token_for(&self, host: &str) -> Option<String> {
+        if let Some(token) = self.hosts.get(host).and_then(|h| h.token.clone()) {
+            return Some(token);
+        }
+        match host {
+            "github.com" => {
+                env_token(&["GH_TOKEN", "GITHUB_TOKEN"]).or_else(|| self.github_token.clone())
+            }
+            "gitlab.com" => env_token(&["GITLAB_TOKEN"]).or_else(|| self.gitlab_token.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn host_config(&self, host: &str) -> HostConfig {
+        self.hosts.get(host).cloned().unwrap_or_default()
+    }
+
     fn ensure_abs(&mut self) {
         self.install_location = tilde(&self.install_location).to_string();
         self.default_code_host = tilde(&self.default_code_host).to_string();
+        self.cache_dir = tilde(&self.cache_dir).to_string();
+        for host in self.hosts.values_mut() {
+            if let Some(ca_cert) = &host.ca_cert {
+                host.ca_cert = Some(tilde(ca_cert).to_string());
+            }
+        }
     }
 
     pub fn save(&mut self) -> Result<()> {
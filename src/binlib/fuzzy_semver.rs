@@ -1,22 +1,227 @@
-use semver::Version;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::OnceLock;
 
-use anyhow::{anyhow, Result};
+use semver::{BuildMetadata, Prerelease, Version};
 
-fn trim_first_char(s: &str) -> Option<&str> {
-    s.chars().next().map(|c| &s[c.len_utf8()..])
+use anyhow::{anyhow, ensure, Error, Result};
+
+use regex::Regex;
+
+use serde::{Deserialize, Serialize};
+
+/// Splits a tag into an optional name prefix, its numeric components, prerelease and build metadata.
+///
+/// The prefix holds no digit, so that it never swallows part of the version, and is only tried
+/// when the tag is not a version by itself. A prerelease starts with a letter, so that dates such as
+/// `2024-05-01` are not read as a version and a prerelease.
+const TAG_PATTERN: &str = r"^(?:[^\d]*?[-_/@])??[vV]?(\d+(?:\.\d+)*)(?:-([A-Za-z][0-9A-Za-z.-]*))?(?:\+([0-9A-Za-z.-]+))?$";
+
+/// Parses a version string strictly, allowing only a leading `v`.
+pub fn parse_version_strict(version: &str) -> Result<Version> {
+    Ok(Version::parse(
+        version.strip_prefix('v').unwrap_or(version),
+    )?)
 }
 
+/// Strips leading zeros from numeric identifiers, which semver forbids.
+fn normalize_identifiers(raw: &str) -> String {
+    raw.split('.')
+        .map(|id| match id.parse::<u64>() {
+            Ok(n) => n.to_string(),
+            _ => String::from(id),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Normalizes a release tag into a version.
+///
+/// Besides plain versions, this understands name prefixes (`release-1.2.3`, `tool-v1.2.3`),
+/// partial versions (`1.2`), extra components (`1.2.3.4`, kept as build metadata) and
+/// zero-padded calendar versions (`2024.05.01`).
 pub fn parse_version_fuzzy(version: &str) -> Result<Version> {
-    match Version::parse(version) {
-        Ok(v) => Ok(v),
-        Err(e) => {
-            if version.starts_with('v') {
-                Ok(Version::parse(trim_first_char(version).ok_or_else(
-                    || anyhow!("Could not parse version string"),
-                )?)?)
-            } else {
-                Err(e.into())
-            }
+    if let Ok(v) = parse_version_strict(version) {
+        return Ok(v);
+    }
+
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let caps = PATTERN
+        .get_or_init(|| Regex::new(TAG_PATTERN).unwrap())
+        .captures(version)
+        .ok_or_else(|| anyhow!("Could not parse version string \"{}\"", version))?;
+
+    let components = caps[1]
+        .split('.')
+        .map(str::parse::<u64>)
+        .collect::<Result<Vec<_>, _>>()?;
+    let component = |i: usize| components.get(i).copied().unwrap_or(0);
+
+    let mut build = components
+        .iter()
+        .skip(3)
+        .map(u64::to_string)
+        .collect::<Vec<_>>();
+    if let Some(meta) = caps.get(3) {
+        build.push(String::from(meta.as_str()));
+    }
+
+    let mut v = Version::new(component(0), component(1), component(2));
+    if let Some(pre) = caps.get(2) {
+        v.pre = Prerelease::new(&normalize_identifiers(pre.as_str()))?;
+    }
+    v.build = BuildMetadata::new(&build.join("."))?;
+    Ok(v)
+}
+
+/// Normalizes a release tag into a version, extracting it with a tag pattern first if any.
+pub fn parse_tag(tag: &str, pattern: Option<&TagPattern>) -> Result<Version> {
+    match pattern {
+        Some(pattern) => {
+            let raw = pattern
+                .version(tag)
+                .ok_or_else(|| anyhow!("Tag \"{}\" does not match {}", tag, pattern))?;
+            parse_version_fuzzy(raw)
         }
+        None => parse_version_fuzzy(tag),
+    }
+}
+
+/// A regex locating the version within release tags, for repositories with unusual tags.
+///
+/// The version is taken from the `version` capture group, or from the first group.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TagPattern(Regex);
+
+impl TagPattern {
+    pub fn new(raw: &str) -> Result<TagPattern> {
+        let regex = Regex::new(raw)?;
+        ensure!(
+            regex.captures_len() > 1,
+            "Tag pattern \"{}\" has no capture group for the version",
+            raw
+        );
+        Ok(TagPattern(regex))
+    }
+
+    /// A pattern matching the tags made of a prefix followed by a version, e.g. `cli-v1.2.3`.
+    pub fn from_prefix(prefix: &str) -> TagPattern {
+        let raw = format!(r"^{}[vV]?(?P<version>\d.*)$", regex::escape(prefix));
+        TagPattern(Regex::new(&raw).unwrap())
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Whether a tag follows the pattern.
+    pub fn is_match(&self, tag: &str) -> bool {
+        self.version(tag).is_some()
+    }
+
+    /// The version part of a tag, if it follows the pattern.
+    pub fn version<'t>(&self, tag: &'t str) -> Option<&'t str> {
+        let caps = self.0.captures(tag)?;
+        caps.name("version")
+            .or_else(|| caps.get(1))
+            .map(|m| m.as_str())
+    }
+}
+
+impl PartialEq for TagPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for TagPattern {}
+
+impl fmt::Display for TagPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for TagPattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        TagPattern::new(s)
+    }
+}
+
+impl TryFrom<String> for TagPattern {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        TagPattern::new(&value)
+    }
+}
+
+impl From<TagPattern> for String {
+    fn from(pattern: TagPattern) -> Self {
+        String::from(pattern.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fuzzy(tag: &str) -> String {
+        parse_version_fuzzy(tag).unwrap().to_string()
+    }
+
+    #[test]
+    fn parses_plain_versions() {
+        assert_eq!(fuzzy("1.2.3"), "1.2.3");
+        assert_eq!(fuzzy("v1.2.3"), "1.2.3");
+        assert_eq!(fuzzy("v1.2.3-rc.1"), "1.2.3-rc.1");
+    }
+
+    #[test]
+    fn parses_prefixed_tags() {
+        assert_eq!(fuzzy("release-1.2.3"), "1.2.3");
+        assert_eq!(fuzzy("tool-v1.2.3"), "1.2.3");
+        assert_eq!(fuzzy("cli/v2.0.0-beta.2"), "2.0.0-beta.2");
+    }
+
+    #[test]
+    fn parses_partial_and_extra_components() {
+        assert_eq!(fuzzy("1.2"), "1.2.0");
+        assert_eq!(fuzzy("v1"), "1.0.0");
+        assert_eq!(fuzzy("1.2.3.4"), "1.2.3+4");
+    }
+
+    #[test]
+    fn parses_calendar_versions() {
+        assert_eq!(fuzzy("2024.05.01"), "2024.5.1");
+        assert_eq!(fuzzy("v2024.05"), "2024.5.0");
+    }
+
+    #[test]
+    fn prefix_does_not_swallow_the_version() {
+        assert!(parse_version_fuzzy("1.2.3-rc_1").is_err());
+        assert!(parse_version_fuzzy("nightly-2024-05-01").is_err());
+        assert!(parse_version_fuzzy("nightly").is_err());
+    }
+
+    #[test]
+    fn tag_pattern_extracts_the_version() {
+        let pattern = TagPattern::from_prefix("cli-v");
+        assert_eq!(
+            parse_tag("cli-v1.4.0", Some(&pattern)).unwrap().to_string(),
+            "1.4.0"
+        );
+        assert!(parse_tag("agent-v1.4.0", Some(&pattern)).is_err());
+
+        let pattern = TagPattern::new(r"^build-(\d+\.\d+)-final$").unwrap();
+        assert_eq!(
+            parse_tag("build-3.1-final", Some(&pattern))
+                .unwrap()
+                .to_string(),
+            "3.1.0"
+        );
     }
 }
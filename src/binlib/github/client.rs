@@ -1,36 +1,121 @@
-use anyhow::{ensure, Result};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, ensure, Result};
 
 use http::status::StatusCode;
 
 use reqwest::{self, header};
 
-use super::{Release, Repository};
+use async_trait::async_trait;
+
+use serde::de::DeserializeOwned;
+
+use super::release::ReleaseResponse;
+use crate::http::HttpClient;
+use crate::source::{
+    self, Endpoint, Release, ReleaseSource, Repository, StopCondition, RELEASES_PER_PAGE,
+};
+use crate::ApiFlavor;
+
+static RELEASES_PATTERN: &str = "{api}/repos/{owner}/{name}/releases";
+
+/// Longest we are willing to sleep for the rate limit to reset before giving up.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(15 * 60);
+
+/// Rate limit status, as reported by the `X-RateLimit-*` response headers.
+#[derive(Debug)]
+struct RateLimit {
+    limit: u64,
+    remaining: u64,
+    reset: u64,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &header::HeaderMap) -> Option<RateLimit> {
+        let get = |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+        Some(RateLimit {
+            limit: get("x-ratelimit-limit")?,
+            remaining: get("x-ratelimit-remaining")?,
+            reset: get("x-ratelimit-reset")?,
+        })
+    }
 
+    fn reset_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.reset)
+    }
+
+    fn time_until_reset(&self) -> Duration {
+        self.reset_time()
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+    }
+}
+
+/// Client for the GitHub API, also spoken by Gitea and Forgejo.
 pub struct Client {
-    client: reqwest::Client,
+    http: HttpClient,
+    api_url: String,
+    flavor: ApiFlavor,
+    authenticated: bool,
+    wait_on_rate_limit: bool,
+    use_latest_endpoint: bool,
 }
 
 impl Client {
-    pub fn new() -> Result<Client> {
+    pub fn new(
+        endpoint: &Endpoint,
+        flavor: ApiFlavor,
+        wait_on_rate_limit: bool,
+        use_latest_endpoint: bool,
+    ) -> Result<Client> {
         let mut headers = header::HeaderMap::new();
         headers.insert(
             header::CONTENT_TYPE,
             header::HeaderValue::from_static("application/json"),
         );
-        headers.insert(
-            header::USER_AGENT,
-            header::HeaderValue::from_static("binman"),
-        );
+
+        let mut http = HttpClient::new(endpoint, headers)?;
+        if let Some(token) = &endpoint.token {
+            // Gitea and Forgejo only accept access tokens with the `token` scheme.
+            let scheme = if flavor == ApiFlavor::Github {
+                "Bearer"
+            } else {
+                "token"
+            };
+            http =
+                http.with_credentials(header::AUTHORIZATION, &format!("{} {}", scheme, token))?;
+        }
 
         Ok(Client {
-            client: reqwest::Client::builder()
-                .default_headers(headers)
-                .build()?,
+            http,
+            api_url: endpoint.api_url.clone(),
+            flavor,
+            authenticated: endpoint.token.is_some(),
+            wait_on_rate_limit,
+            use_latest_endpoint,
         })
     }
 
-    pub fn get_repository(&self, url: &str) -> Result<Repository> {
-        Repository::from_url(url)
+    fn releases_url(&self, repo: &Repository) -> String {
+        RELEASES_PATTERN
+            .replace("{api}", &self.api_url)
+            .replace("{owner}", &repo.owner)
+            .replace("{name}", &repo.name)
+    }
+
+    fn first_page_url(&self, repo: &Repository) -> String {
+        // Gitea and Forgejo name the page size differently, and cap it lower.
+        let size_param = if self.flavor == ApiFlavor::Github {
+            "per_page"
+        } else {
+            "limit"
+        };
+        format!(
+            "{}?{}={}",
+            self.releases_url(repo),
+            size_param,
+            RELEASES_PER_PAGE
+        )
     }
 
     fn validate_response(&self, resp: &reqwest::Response) -> Result<()> {
@@ -40,27 +125,113 @@ impl Client {
             status_code != not_found,
             "Repository or owner does not exist"
         );
+        ensure!(
+            status_code.is_success(),
+            "API request to {} failed - status: {}",
+            resp.url(),
+            status_code
+        );
         Ok(())
     }
 
-    pub async fn latest_release(&self, repo: &Repository) -> Result<Release> {
-        let release_url = repo.releases_url();
+    fn is_rate_limited(resp: &reqwest::Response, rate_limit: Option<&RateLimit>) -> bool {
+        let status = resp.status();
+        (status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS)
+            && rate_limit.map(|r| r.remaining == 0).unwrap_or(false)
+    }
+
+    fn rate_limit_error(&self, rate_limit: &RateLimit) -> anyhow::Error {
+        let hint = if self.authenticated {
+            ""
+        } else {
+            " - configure a token to raise the limit"
+        };
+        anyhow!(
+            "API rate limit of {} requests exhausted, resets at {} (in {}s){}",
+            rate_limit.limit,
+            humantime::format_rfc3339_seconds(rate_limit.reset_time()),
+            rate_limit.time_until_reset().as_secs(),
+            hint
+        )
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        Ok(self.get(url).await?.json().await?)
+    }
+
+    async fn get_release_page(&self, url: String) -> Result<(Vec<Release>, Option<String>)> {
+        let resp = self.get(&url).await?;
+        let next_url = source::next_page_url(resp.headers());
+        let releases: Vec<ReleaseResponse> = resp.json().await?;
+        Ok((releases.into_iter().map(Release::from).collect(), next_url))
+    }
+
+    async fn get(&self, url: &str) -> Result<reqwest::Response> {
+        let mut waited = false;
+        loop {
+            let resp = self.http.send(url).await?;
+            let rate_limit = RateLimit::from_headers(resp.headers());
+
+            if let Some(rate_limit) = &rate_limit {
+                tracing::debug!(
+                    remaining = rate_limit.remaining,
+                    limit = rate_limit.limit,
+                    "github rate limit"
+                );
+            }
 
-        let resp = self.client.get(&release_url).send().await?;
-        let release_json = resp.text().await?;
-        let release: Vec<Release> = serde_json::from_str(&release_json)?;
+            if Self::is_rate_limited(&resp, rate_limit.as_ref()) {
+                let rate_limit = rate_limit.unwrap();
+                let wait = rate_limit.time_until_reset() + Duration::from_secs(1);
+                if !self.wait_on_rate_limit || waited || wait > MAX_RATE_LIMIT_WAIT {
+                    bail!(self.rate_limit_error(&rate_limit));
+                }
+                tracing::warn!(
+                    seconds = wait.as_secs(),
+                    "rate limit exhausted - waiting for reset"
+                );
+                tokio::time::sleep(wait).await;
+                waited = true;
+                continue;
+            }
 
-        ensure!(!release.is_empty(), "No releases found");
+            self.validate_response(&resp)?;
+            return Ok(resp);
+        }
+    }
+}
 
-        Ok(release.into_iter().next().unwrap())
+#[async_trait]
+impl ReleaseSource for Client {
+    fn http_client(&self) -> &HttpClient {
+        &self.http
     }
 
-    pub async fn get_releases(&self, repo: &Repository) -> Result<Vec<Release>> {
-        let releases_url = repo.releases_url();
-        let resp = self.client.get(&releases_url).send().await?;
-        self.validate_response(&resp)?;
-        let releases_json = resp.text().await?;
-        let releases: Vec<Release> = serde_json::from_str(&releases_json)?;
-        Ok(releases)
+    async fn latest_release(
+        &self,
+        repo: &Repository,
+        include_prereleases: bool,
+    ) -> Result<Release> {
+        // The endpoint never returns prereleases.
+        if self.use_latest_endpoint && !include_prereleases {
+            // Lets the host decide, which also honors releases manually marked as latest.
+            let url = format!("{}/latest", self.releases_url(repo));
+            let release: ReleaseResponse = self.get_json(&url).await?;
+            return Ok(release.into());
+        }
+
+        Release::latest(self.get_releases(repo).await?, include_prereleases)
+            .ok_or_else(|| anyhow!("No release found"))
+    }
+
+    async fn list_releases(
+        &self,
+        repo: &Repository,
+        stop: StopCondition<'_>,
+    ) -> Result<Vec<Release>> {
+        source::paginate(self.first_page_url(repo), stop, |url| {
+            self.get_release_page(url)
+        })
+        .await
     }
 }
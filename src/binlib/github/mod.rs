@@ -1,9 +1,4 @@
-mod asset;
 mod client;
 mod release;
-mod repository;
 
-pub use asset::Asset;
 pub use client::Client;
-pub use release::Release;
-pub use repository::Repository;
@@ -1,52 +1,45 @@
-use rood::sys::{Architecture, Platform};
-
-use semver::{Prerelease, Version};
-
 use serde::Deserialize;
 
-use crate::fuzzy_semver::parse_version_fuzzy;
+use crate::source::{Asset, Release};
 
-use super::Asset;
+#[derive(Deserialize)]
+struct AssetResponse {
+    name: String,
+    browser_download_url: String,
+    updated_at: Option<String>,
+}
 
-#[derive(Clone, Debug, Deserialize)]
-pub struct Release {
+/// A release, as returned by the GitHub API.
+#[derive(Deserialize)]
+pub struct ReleaseResponse {
     tag_name: String,
-    assets: Vec<Asset>,
+    assets: Vec<AssetResponse>,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    published_at: Option<String>,
 }
 
-impl Release {
-    pub fn version(&self) -> Version {
-        parse_version_fuzzy(&self.tag_name).unwrap_or_else(|_| {
-            let mut v = Version::new(0, 0, 0);
-            v.pre = Prerelease::new(&self.tag_name).unwrap();
-            v
-        })
-    }
-
-    pub fn platform_assets(&self) -> Vec<&Asset> {
-        let cur_platform = Platform::detect();
-        let cur_arch = Architecture::detect();
-
-        // Hack to support Apple Silicon + Rosetta
-        let fallback_architectures =
-            if cur_platform == Platform::Darwin && cur_arch == Architecture::Arm64 {
-                vec![Architecture::Arm64, Architecture::Amd64]
-            } else {
-                vec![cur_arch]
-            };
-
-        for arch in fallback_architectures {
-            let arch_assets = self
-                .assets
+impl From<ReleaseResponse> for Release {
+    fn from(resp: ReleaseResponse) -> Release {
+        // Assets of a moving tag may be replaced without the release being published again.
+        let revision = resp
+            .assets
+            .iter()
+            .filter_map(|a| a.updated_at.clone())
+            .chain(resp.published_at.clone())
+            .max();
+
+        Release::new(
+            &resp.tag_name,
+            resp.assets
                 .iter()
-                .filter(|asset| asset.platform() == cur_platform && asset.architecture() == arch)
-                .collect::<Vec<_>>();
-
-            if !arch_assets.is_empty() {
-                return arch_assets;
-            }
-        }
-
-        Vec::default()
+                .map(|a| Asset::new(&a.name, &a.browser_download_url))
+                .collect(),
+            resp.draft,
+            resp.prerelease,
+            revision,
+        )
     }
 }
@@ -0,0 +1,90 @@
+use anyhow::{anyhow, ensure, Result};
+
+use async_trait::async_trait;
+
+use http::status::StatusCode;
+
+use reqwest::{self, header};
+
+use super::release::ReleaseResponse;
+use crate::http::HttpClient;
+use crate::source::{
+    self, Endpoint, Release, ReleaseSource, Repository, StopCondition, RELEASES_PER_PAGE,
+};
+
+static RELEASES_PATTERN: &str = "{api}/projects/{project}/releases";
+
+pub struct Client {
+    http: HttpClient,
+    api_url: String,
+}
+
+impl Client {
+    pub fn new(endpoint: &Endpoint) -> Result<Client> {
+        let mut http = HttpClient::new(endpoint, header::HeaderMap::new())?;
+        if let Some(token) = &endpoint.token {
+            // Unlike `PRIVATE-TOKEN`, the standard header is dropped on redirects to other hosts.
+            http = http.with_credentials(header::AUTHORIZATION, &format!("Bearer {}", token))?;
+        }
+
+        Ok(Client {
+            http,
+            api_url: endpoint.api_url.clone(),
+        })
+    }
+
+    fn releases_url(&self, repo: &Repository) -> String {
+        // Projects are addressed by their URL-encoded full path.
+        RELEASES_PATTERN
+            .replace("{api}", &self.api_url)
+            .replace("{project}", &repo.path().replace('/', "%2F"))
+    }
+
+    async fn get_release_page(&self, url: String) -> Result<(Vec<Release>, Option<String>)> {
+        let resp = self.get(&url).await?;
+        let next_url = source::next_page_url(resp.headers());
+        let releases: Vec<ReleaseResponse> = resp.json().await?;
+        Ok((releases.into_iter().map(Release::from).collect(), next_url))
+    }
+
+    async fn get(&self, url: &str) -> Result<reqwest::Response> {
+        let resp = self.http.send(url).await?;
+        let status_code = resp.status();
+        ensure!(
+            status_code != StatusCode::NOT_FOUND,
+            "Project does not exist"
+        );
+        ensure!(
+            status_code.is_success(),
+            "GitLab API request to {} failed - status: {}",
+            resp.url(),
+            status_code
+        );
+        Ok(resp)
+    }
+}
+
+#[async_trait]
+impl ReleaseSource for Client {
+    fn http_client(&self) -> &HttpClient {
+        &self.http
+    }
+
+    async fn latest_release(
+        &self,
+        repo: &Repository,
+        include_prereleases: bool,
+    ) -> Result<Release> {
+        Release::latest(self.get_releases(repo).await?, include_prereleases)
+            .ok_or_else(|| anyhow!("No release found"))
+    }
+
+    async fn list_releases(
+        &self,
+        repo: &Repository,
+        stop: StopCondition<'_>,
+    ) -> Result<Vec<Release>> {
+        let first_page_url = format!("{}?per_page={}", self.releases_url(repo), RELEASES_PER_PAGE);
+        source::paginate(first_page_url, stop, |url| self.get_release_page(url)).await
+    }
+}
@@ -0,0 +1,4 @@
+mod client;
+mod release;
+
+pub use client::Client;
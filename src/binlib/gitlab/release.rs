@@ -0,0 +1,74 @@
+use serde::Deserialize;
+
+use crate::source::{Asset, Release};
+
+#[derive(Deserialize)]
+struct LinkResponse {
+    name: String,
+    url: String,
+    direct_asset_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AssetsResponse {
+    #[serde(default)]
+    links: Vec<LinkResponse>,
+}
+
+/// A release, as returned by the GitLab API.
+#[derive(Deserialize)]
+pub struct ReleaseResponse {
+    tag_name: String,
+    assets: AssetsResponse,
+    #[serde(default)]
+    upcoming_release: bool,
+    released_at: Option<String>,
+}
+
+impl From<ReleaseResponse> for Release {
+    fn from(resp: ReleaseResponse) -> Release {
+        Release::new(
+            &resp.tag_name,
+            resp.assets
+                .links
+                .iter()
+                .map(|l| Asset::new(&l.name, l.direct_asset_url.as_ref().unwrap_or(&l.url)))
+                .collect(),
+            // Upcoming releases have no assets yet, they are held back like drafts.
+            resp.upcoming_release,
+            false,
+            resp.released_at.clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(json: &str) -> Release {
+        serde_json::from_str::<ReleaseResponse>(json)
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn holds_back_upcoming_releases() {
+        let upcoming = release(
+            r#"{"tag_name": "v2.0.0", "assets": {}, "upcoming_release": true, "released_at": "2099-01-01T00:00:00Z"}"#,
+        );
+        assert!(upcoming.is_draft());
+        assert!(!upcoming.is_prerelease());
+
+        let released = release(
+            r#"{"tag_name": "v1.0.0", "assets": {}, "released_at": "2024-01-01T00:00:00Z"}"#,
+        );
+        assert!(!released.is_draft());
+        assert_eq!(
+            Release::latest(vec![upcoming, released], true)
+                .unwrap()
+                .tag_name(),
+            "v1.0.0"
+        );
+    }
+}
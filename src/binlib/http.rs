@@ -0,0 +1,363 @@
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Context, Result};
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{Certificate, RequestBuilder, Response, StatusCode, Url};
+
+use sha2::{Digest, Sha256};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::source::Endpoint;
+
+/// Longest wait to establish a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest wait for API responses, from sending the request to reading the body.
+const API_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest wait for the response headers of a download.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest wait for the next chunk of a download.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Attempts made for a request before giving up on transient failures.
+const MAX_ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry, doubled on each subsequent one.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Receives the bytes received so far and the expected total, if known.
+pub type ProgressCallback<'a> = &'a (dyn Fn(u64, Option<u64>) + Send + Sync);
+
+/// Caps the downloads running at once, shared by all the packages of a command.
+#[derive(Clone, Debug)]
+pub struct DownloadLimit {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+}
+
+impl DownloadLimit {
+    /// A limit of `max` downloads at once, at least one.
+    pub fn new(max: usize) -> DownloadLimit {
+        let max = max.max(1);
+        DownloadLimit {
+            semaphore: Arc::new(Semaphore::new(max)),
+            max,
+        }
+    }
+
+    /// The most downloads allowed at once.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Waits for a download slot, released when the permit is dropped.
+    pub async fn acquire(&self) -> Result<SemaphorePermit<'_>> {
+        Ok(self.semaphore.acquire().await?)
+    }
+}
+
+/// An error worth retrying, such as a server error or a dropped connection.
+#[derive(Debug)]
+struct Transient(anyhow::Error);
+
+impl std::fmt::Display for Transient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for Transient {}
+
+/// Marks timeouts and connection failures as transient.
+fn classify(err: reqwest::Error) -> anyhow::Error {
+    if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() {
+        Transient(err.into()).into()
+    } else {
+        err.into()
+    }
+}
+
+/// Runs an operation, retrying it with exponential backoff while it fails transiently.
+async fn with_retries<T, F, Fut>(url: &str, operation: F) -> Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if attempt < MAX_ATTEMPTS && e.is::<Transient>() => {
+                tracing::warn!(url=%url, attempt = attempt, error=%e, "request failed - retrying in {}ms", backoff.as_millis());
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(match e.downcast::<Transient>() {
+                    Ok(Transient(inner)) => inner,
+                    Err(e) => e,
+                })
+            }
+            Ok(value) => return Ok(value),
+        }
+    }
+}
+
+/// HTTP client shared by the API clients and asset downloads.
+///
+/// Requests are retried with exponential backoff on server errors and connection failures.
+/// Credentials are only sent to the hosts of the endpoint they were configured for.
+#[derive(Clone, Debug)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    credentials: Option<(header::HeaderName, HeaderValue)>,
+    trusted_hosts: Vec<String>,
+}
+
+impl HttpClient {
+    /// A client trusting the configured CA of an endpoint, with the given default headers.
+    pub fn new(endpoint: &Endpoint, mut headers: HeaderMap) -> Result<HttpClient> {
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("binman"));
+
+        let mut builder = reqwest::Client::builder()
+            .default_headers(headers)
+            .connect_timeout(CONNECT_TIMEOUT);
+        if let Some(ca_cert) = &endpoint.ca_cert {
+            let pem = fs::read(ca_cert)
+                .with_context(|| format!("failed to read CA certificate {}", ca_cert))?;
+            builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+
+        let api_host = Url::parse(&endpoint.api_url)?
+            .host_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow!("API URL {} has no host", endpoint.api_url))?;
+
+        Ok(HttpClient {
+            client: builder.build()?,
+            credentials: None,
+            trusted_hosts: vec![api_host, endpoint.host.clone()],
+        })
+    }
+
+    /// Sends a header holding credentials along with requests to the endpoint.
+    pub fn with_credentials(mut self, name: header::HeaderName, value: &str) -> Result<HttpClient> {
+        let mut value = HeaderValue::from_str(value)?;
+        value.set_sensitive(true);
+        self.credentials = Some((name, value));
+        Ok(self)
+    }
+
+    fn request(&self, url: &str) -> Result<RequestBuilder> {
+        let parsed = Url::parse(url)?;
+        let mut request = self.client.get(parsed.clone());
+        if let Some((name, value)) = &self.credentials {
+            let trusted = parsed
+                .host_str()
+                .map(|host| self.trusted_hosts.iter().any(|h| h == host))
+                .unwrap_or(false);
+            if trusted {
+                request = request.header(name.clone(), value.clone());
+            }
+        }
+        Ok(request)
+    }
+
+    async fn send_once(&self, request: RequestBuilder) -> Result<Response> {
+        let resp = request.send().await.map_err(classify)?;
+        if resp.status().is_server_error() {
+            return Err(Transient(anyhow!(
+                "Request to {} failed - status: {}",
+                resp.url(),
+                resp.status()
+            ))
+            .into());
+        }
+        Ok(resp)
+    }
+
+    /// Sends an API request, leaving the validation of non-server errors to the caller.
+    pub async fn send(&self, url: &str) -> Result<Response> {
+        with_retries(url, || async {
+            self.send_once(self.request(url)?.timeout(API_TIMEOUT))
+                .await
+        })
+        .await
+    }
+
+    /// Fetches a small text document, such as a checksums file.
+    pub async fn get_text(&self, url: &str) -> Result<String> {
+        with_retries(url, || async {
+            let resp = self
+                .send_once(self.request(url)?.timeout(API_TIMEOUT))
+                .await?;
+            validate(&resp)?;
+            resp.text().await.map_err(classify)
+        })
+        .await
+    }
+
+    async fn download_once(
+        &self,
+        url: &str,
+        partial: &Path,
+        on_progress: ProgressCallback<'_>,
+    ) -> Result<String> {
+        let validator_path = partial.with_extension("validator");
+        let mut offset = fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+
+        let mut request = self.request(url)?;
+        if offset > 0 {
+            match fs::read_to_string(&validator_path) {
+                // The whole file is sent again if it changed since the partial download.
+                Ok(validator) => {
+                    request = request
+                        .header(header::RANGE, format!("bytes={}-", offset))
+                        .header(header::IF_RANGE, validator);
+                }
+                // Without a validator, the rest of the file could come from another build.
+                Err(_) => {
+                    tracing::debug!(url=%url, "discarding partial download without validator");
+                    fs::remove_file(partial)?;
+                    offset = 0;
+                }
+            }
+        }
+        let mut resp = tokio::time::timeout(RESPONSE_TIMEOUT, self.send_once(request))
+            .await
+            .map_err(|_| Transient(anyhow!("Timed out waiting for a response from {}", url)))??;
+
+        let mut hasher = Sha256::new();
+        let mut downloaded = 0;
+        let mut file = match resp.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                if offset == 0 || content_range_start(&resp) != Some(offset) {
+                    discard_partial(partial, &validator_path)?;
+                    return Err(Transient(anyhow!(
+                        "Unexpected range received for {}, restarting the download",
+                        url
+                    ))
+                    .into());
+                }
+                downloaded = offset;
+                tracing::debug!(url=%url, offset = offset, "resuming download");
+                io::copy(&mut File::open(partial)?, &mut hasher)?;
+                OpenOptions::new().append(true).open(partial)?
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                discard_partial(partial, &validator_path)?;
+                return Err(Transient(anyhow!("Partial download of {} is stale", url)).into());
+            }
+            _ => {
+                validate(&resp)?;
+                let validator = resp
+                    .headers()
+                    .get(header::ETAG)
+                    .or_else(|| resp.headers().get(header::LAST_MODIFIED))
+                    .and_then(|v| v.to_str().ok());
+                match validator {
+                    Some(validator) => fs::write(&validator_path, validator)?,
+                    None => {
+                        let _ = fs::remove_file(&validator_path);
+                    }
+                }
+                File::create(partial)?
+            }
+        };
+
+        let total = resp.content_length().map(|len| len + downloaded);
+        on_progress(downloaded, total);
+
+        loop {
+            let chunk = tokio::time::timeout(READ_TIMEOUT, resp.chunk())
+                .await
+                .map_err(|_| Transient(anyhow!("Timed out reading from {}", url)))?
+                .map_err(classify)?;
+            match chunk {
+                Some(chunk) => {
+                    file.write_all(&chunk)?;
+                    hasher.update(&chunk);
+                    downloaded += chunk.len() as u64;
+                    on_progress(downloaded, total);
+                }
+                None => break,
+            }
+        }
+        file.flush()?;
+
+        let _ = fs::remove_file(&validator_path);
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Streams a file to disk and returns its SHA-256 digest.
+    ///
+    /// Data already present at `partial` is resumed with a range request, which also happens
+    /// after transient failures. Progress is reported as bytes received out of the expected total.
+    pub async fn download(
+        &self,
+        url: &str,
+        partial: &Path,
+        on_progress: ProgressCallback<'_>,
+    ) -> Result<String> {
+        if let Some(parent) = partial.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        with_retries(url, || self.download_once(url, partial, on_progress)).await
+    }
+}
+
+/// Removes a partial download along with its validator, so that it starts over.
+fn discard_partial(partial: &Path, validator_path: &Path) -> Result<()> {
+    fs::remove_file(partial)?;
+    let _ = fs::remove_file(validator_path);
+    Ok(())
+}
+
+/// The offset of the first byte of a partial response, from its `Content-Range` header.
+fn content_range_start(resp: &Response) -> Option<u64> {
+    let value = resp.headers().get(header::CONTENT_RANGE)?.to_str().ok()?;
+    parse_content_range_start(value)
+}
+
+/// Reads the first byte offset of a `Content-Range` value such as `bytes 100-199/200`.
+fn parse_content_range_start(value: &str) -> Option<u64> {
+    let range = value.trim().strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+/// Turns an unsuccessful status into an error, so that error pages are never used as content.
+fn validate(resp: &Response) -> Result<()> {
+    let status: StatusCode = resp.status();
+    ensure!(
+        status.is_success(),
+        "Request to {} failed - status: {}",
+        resp.url(),
+        status
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_content_range_start() {
+        assert_eq!(parse_content_range_start("bytes 100-199/200"), Some(100));
+        assert_eq!(parse_content_range_start("bytes 0-0/*"), Some(0));
+        assert_eq!(parse_content_range_start("bytes */200"), None);
+        assert_eq!(parse_content_range_start("items 100-199/200"), None);
+        assert_eq!(parse_content_range_start("bytes abc-199/200"), None);
+    }
+}
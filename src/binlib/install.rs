@@ -1,26 +1,307 @@
+use std::collections::HashMap;
 use std::fs;
-use std::fs::File;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use futures::future::try_join_all;
 
 use rood::sys::file::{self, ensure_exists};
 
-use sha2::{Digest, Sha256};
+use semver::Version;
+
+use serde::{Deserialize, Serialize};
 
-use tempfile::tempdir;
+use tempfile::{tempdir, TempDir};
 
 use walkdir::WalkDir;
 
-use super::fuzzy_semver::parse_version_fuzzy;
+use super::cache::Cache;
+use super::checksum::{self, sha256_file, Checksums};
+use super::fuzzy_semver::{parse_tag, TagPattern};
+use super::http::{DownloadLimit, HttpClient};
+use super::progress::{DownloadEvent, ProgressHandler};
+use super::transaction::ArtifactSwap;
+use super::version_spec::VersionSpec;
 use super::zip;
-use super::{Config, State, StateEntry};
-use crate::github::{Asset, Client, Repository};
+use super::{Channel, Config, State, StateEntry};
+use crate::source::{self, Asset, Release, ReleaseSource, Repository};
+
+/// Options shared by installs and updates.
+#[derive(Clone, Debug, Default)]
+pub struct InstallOptions {
+    /// Whether to refuse assets for which the release publishes no checksum.
+    pub require_checksum: bool,
+
+    /// The exact release to install, such as one recorded in a lockfile.
+    ///
+    /// When set, the release is selected by its tag and the selected assets must be exactly the
+    /// pinned ones, downloaded from the same URLs and with the same digests.
+    pub pinned: Option<Pin>,
+
+    /// The channel to follow, keeping the recorded one on updates when absent.
+    pub channel: Option<Channel>,
+
+    /// The pattern release tags must follow, keeping the recorded one on updates when absent.
+    ///
+    /// Releases with other tags are ignored, so that a monorepo component only sees its own.
+    pub tag_pattern: Option<TagPattern>,
+
+    /// The name to install the package under, defaulting to the repository name.
+    pub name: Option<String>,
+
+    /// The cache of downloads, also keeping partial ones so that they can be resumed.
+    pub cache: Option<Cache>,
+
+    /// Whether to install from the cache only, without network access.
+    pub offline: bool,
+
+    /// Receives the progress of downloads.
+    pub progress: Option<ProgressHandler>,
 
-#[tracing::instrument(skip(install_location))]
-async fn save_asset(asset: &Asset, install_location: &Path) -> Result<()> {
-    let mut asset_dest_path = install_location.join(&format!(
+    /// Caps the downloads running at once, defaulting to the configured maximum.
+    pub download_limit: Option<DownloadLimit>,
+
+    /// Whether to replace files owned by another package, or by no package.
+    pub overwrite: bool,
+}
+
+/// Applies the configuration to the options of an install or update.
+pub fn configure(cfg: &Config, options: &InstallOptions) -> InstallOptions {
+    let mut options = options.clone();
+    options.require_checksum |= cfg.require_checksums;
+    options.cache = Some(Cache::new(&cfg.cache_dir));
+    if options.download_limit.is_none() {
+        options.download_limit = Some(DownloadLimit::new(cfg.max_concurrent_downloads));
+    }
+    options
+}
+
+/// An asset downloaded while staging a release.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct ResolvedAsset {
+    pub file_name: String,
+    pub download_url: String,
+    pub sha256: String,
+}
+
+/// The tag and assets an install is pinned to.
+#[derive(Clone, Debug)]
+pub struct Pin {
+    pub tag: String,
+    pub assets: Vec<ResolvedAsset>,
+}
+
+impl Pin {
+    /// The pinned asset with a file name.
+    fn asset(&self, file_name: &str) -> Result<&ResolvedAsset> {
+        self.assets
+            .iter()
+            .find(|a| a.file_name == file_name)
+            .ok_or_else(|| anyhow!("Asset {} is not pinned", file_name))
+    }
+}
+
+/// The exact release and assets a package was installed from.
+#[derive(Clone, Debug)]
+pub struct Resolution {
+    pub url: String,
+    pub tag: String,
+    pub version: Version,
+    pub assets: Vec<ResolvedAsset>,
+}
+
+fn ensure_online(asset: &Asset, options: &InstallOptions) -> Result<()> {
+    ensure!(
+        !options.offline,
+        "Asset {} is not cached, and downloads are disabled offline",
+        asset.file_name()
+    );
+    Ok(())
+}
+
+/// What identifies the cached copy of an asset.
+#[derive(Clone, Copy, Debug, Default)]
+struct CacheKey<'a> {
+    /// The digest the asset must have, from a checksums file or a pin.
+    sha256: Option<&'a str>,
+
+    /// The revision of the release, recorded along with downloads.
+    revision: Option<&'a str>,
+
+    /// Whether a copy downloaded from the same URL for the same revision may be reused.
+    by_url: bool,
+}
+
+impl CacheKey<'_> {
+    /// The cached copy of an asset: the file with the expected digest when it is known, or else
+    /// the one downloaded from the same URL for the same revision.
+    fn lookup(&self, cache: &Cache, asset: &Asset) -> Result<Option<(PathBuf, String)>> {
+        if let Some(sha256) = self.sha256 {
+            let blob = cache.lookup_digest(sha256)?;
+            return Ok(blob.map(|blob| (blob, sha256.to_lowercase())));
+        }
+        match self.revision.filter(|_| self.by_url) {
+            Some(revision) => Ok(cache
+                .lookup(&asset.download_url, revision)?
+                .map(|(blob, entry)| (blob, entry.sha256))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Fetches a small text asset, such as a checksums file, from the cache if possible.
+async fn fetch_text(
+    client: &HttpClient,
+    asset: &Asset,
+    key: CacheKey<'_>,
+    options: &InstallOptions,
+) -> Result<String> {
+    if let Some((blob, _)) = options
+        .cache
+        .as_ref()
+        .map(|c| key.lookup(c, asset))
+        .transpose()?
+        .flatten()
+    {
+        return Ok(fs::read_to_string(blob)?);
+    }
+
+    ensure_online(asset, options)?;
+    let text = client
+        .get_text(&asset.download_url)
+        .await
+        .with_context(|| format!("Failed to download {}", asset.file_name()))?;
+    if let Some(cache) = &options.cache {
+        cache.insert_bytes(
+            &asset.download_url,
+            asset.file_name(),
+            text.as_bytes(),
+            key.revision,
+        )?;
+    }
+    Ok(text)
+}
+
+/// Copies an asset to `dest` from the cache, or downloads it to the cache, and returns its digest.
+async fn fetch_asset(
+    client: &HttpClient,
+    asset: &Asset,
+    dest: &Path,
+    key: CacheKey<'_>,
+    options: &InstallOptions,
+) -> Result<String> {
+    let cache = options.cache.as_ref();
+    if let Some((blob, sha256)) = cache.map(|c| key.lookup(c, asset)).transpose()?.flatten() {
+        fs::copy(blob, dest)?;
+        tracing::info!(asset=%asset.file_name(), "using cached asset");
+        return Ok(sha256);
+    }
+    ensure_online(asset, options)?;
+
+    // Resume any previous attempt kept in the cache.
+    let partial_path = match cache {
+        Some(cache) => cache.partial_path(&asset.download_url)?,
+        None => dest.with_extension("part"),
+    };
+    let emit = |event: DownloadEvent| {
+        if let Some(progress) = &options.progress {
+            progress.emit(event);
+        }
+    };
+    let asset_name = String::from(asset.file_name());
+    let on_progress = |downloaded, total| {
+        emit(DownloadEvent::Progress {
+            asset: asset_name.clone(),
+            downloaded,
+            total,
+        })
+    };
+
+    let _permit = match &options.download_limit {
+        Some(limit) => Some(limit.acquire().await?),
+        None => None,
+    };
+    emit(DownloadEvent::Started {
+        asset: asset_name.clone(),
+    });
+    let digest = client
+        .download(&asset.download_url, &partial_path, &on_progress)
+        .await
+        .with_context(|| format!("Failed to download {}", asset.file_name()))?;
+    emit(DownloadEvent::Finished {
+        asset: asset_name.clone(),
+    });
+    move_file(&partial_path, dest)?;
+    tracing::debug!(path=?dest, "wrote asset");
+
+    if let Some(cache) = cache {
+        cache.insert(
+            &asset.download_url,
+            asset.file_name(),
+            dest,
+            &digest,
+            key.revision,
+        )?;
+    }
+    Ok(digest)
+}
+
+fn verify_asset(
+    asset: &Asset,
+    digest: &str,
+    checksums: &Checksums,
+    options: &InstallOptions,
+) -> Result<()> {
+    if let Some(pin) = &options.pinned {
+        ensure!(
+            digest == pin.asset(asset.file_name())?.sha256,
+            "Asset {} does not match its pinned digest",
+            asset.file_name()
+        );
+        tracing::debug!(asset=%asset.file_name(), "pinned digest ok");
+    }
+
+    match checksums.get(asset.file_name()) {
+        Some(expected_hash) => {
+            ensure!(
+                digest == expected_hash,
+                "Checksum verification failed for {}",
+                asset.file_name()
+            );
+            tracing::debug!(asset=%asset.file_name(), "checksum ok");
+        }
+        None => {
+            ensure!(
+                !options.require_checksum,
+                "No checksum published for {}",
+                asset.file_name()
+            );
+            tracing::debug!(asset=%asset.file_name(), "no checksum published");
+        }
+    }
+    Ok(())
+}
+
+/// Moves a file, copying it when the destination is on another file system.
+fn move_file(src: &Path, dst: &Path) -> Result<()> {
+    if fs::rename(src, dst).is_err() {
+        fs::copy(src, dst)?;
+        fs::remove_file(src)?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip(client, install_location, checksums, key, options))]
+async fn save_asset(
+    client: &HttpClient,
+    asset: &Asset,
+    install_location: &Path,
+    checksums: &Checksums,
+    key: CacheKey<'_>,
+    options: &InstallOptions,
+) -> Result<ResolvedAsset> {
+    let mut asset_dest_path = install_location.join(format!(
         "{}-{}-{}",
         asset.name(),
         asset.platform(),
@@ -29,16 +310,33 @@ async fn save_asset(asset: &Asset, install_location: &Path) -> Result<()> {
     let extension = asset.extension();
     asset_dest_path.set_extension(extension);
 
-    // Download the file
-    let resp = reqwest::get(&asset.browser_download_url).await?;
-    let bytes_buffer = resp.bytes().await?;
-    let body: &[u8] = bytes_buffer.as_ref();
-    let mut dest = File::create(&asset_dest_path)?;
-    dest.write_all(body)?;
-    tracing::debug!(path=?asset_dest_path, "wrote asset");
+    let key = CacheKey {
+        sha256: options
+            .pinned
+            .as_ref()
+            .and_then(|pin| pin.asset(asset.file_name()).ok())
+            .map(|pinned| pinned.sha256.as_str())
+            .or_else(|| checksums.get(asset.file_name())),
+        ..key
+    };
+    let digest = fetch_asset(client, asset, &asset_dest_path, key, options).await?;
+    if let Err(e) = verify_asset(asset, &digest, checksums, options) {
+        if let Some(cache) = &options.cache {
+            cache.remove(&asset.download_url)?;
+        }
+        return Err(e);
+    }
 
-    // Extract, if required.
-    if let Some(compression) = zip::get_compression(extension) {
+    // Extract, if required. Magic bytes are trusted over the extension.
+    let compression =
+        zip::detect_compression(&asset_dest_path)?.or_else(|| zip::get_compression(extension));
+    if let Some(compression) = compression {
+        if extension.is_empty() {
+            // Single compressed files are inflated next to the archive, without its extension.
+            let renamed = asset_dest_path.with_extension(compression.extension());
+            fs::rename(&asset_dest_path, &renamed)?;
+            asset_dest_path = renamed;
+        }
         zip::extract(&asset_dest_path, install_location, compression)
             .context("inflation failed")?;
         tracing::debug!(compression=?compression, destination=?install_location, "inflated compressed asset");
@@ -51,7 +349,11 @@ async fn save_asset(asset: &Asset, install_location: &Path) -> Result<()> {
         tracing::debug!(asset=?asset_dest_path, "made asset executable");
     }
 
-    Ok(())
+    Ok(ResolvedAsset {
+        file_name: String::from(asset.file_name()),
+        download_url: asset.download_url.clone(),
+        sha256: digest,
+    })
 }
 
 fn do_checksum(src_dir: &Path, checksum_file_path: &Path) -> Result<()> {
@@ -73,14 +375,8 @@ fn do_checksum(src_dir: &Path, checksum_file_path: &Path) -> Result<()> {
     let checksum_target_path = src_dir.join(checksum_file_name);
     ensure_exists(&checksum_target_path).context("Checksum target not found")?;
 
-    let mut checksum = Sha256::new();
-    let artifact_data = fs::read(checksum_target_path)?;
-    checksum.update(artifact_data);
-    let checksum_value = checksum.finalize();
-    let nicely_formatted_hash = format!("{:x}", checksum_value);
-
     ensure!(
-        nicely_formatted_hash == expected_hash,
+        sha256_file(&checksum_target_path)? == expected_hash,
         "Checksum verification failed for {}",
         checksum_file_name
     );
@@ -92,8 +388,8 @@ fn do_checksum(src_dir: &Path, checksum_file_path: &Path) -> Result<()> {
 }
 
 #[tracing::instrument]
-fn move_assets(src_dir: &Path, dst_dir: &Path) -> Result<Vec<String>> {
-    let mut final_assets = Vec::new();
+fn stage_assets(src_dir: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut staged_assets = Vec::new();
     let wk = WalkDir::new(src_dir);
     for entry in wk.into_iter().filter_map(|e| e.ok()) {
         if entry.path().is_dir() {
@@ -118,91 +414,335 @@ fn move_assets(src_dir: &Path, dst_dir: &Path) -> Result<Vec<String>> {
         let current_file_name = raw_fn.to_str().unwrap();
         let mut final_file_name = PathBuf::from(current_file_name.split('-').next().unwrap());
         final_file_name.set_extension(entry.path().extension().unwrap_or_default());
-        let dst_entry = dst_dir.join(&final_file_name);
 
         if file::is_executable(entry.path())? {
-            fs::copy(entry.path(), &dst_entry)?;
-            file::make_executable(&dst_entry)?;
-            final_assets.push(String::from(dst_entry.to_str().unwrap()));
-            tracing::debug!("produced asset {}", dst_entry.to_str().unwrap());
+            staged_assets.push((entry.path().to_path_buf(), final_file_name));
         }
     }
 
-    Ok(final_assets)
+    Ok(staged_assets)
 }
 
-pub async fn async_install(
-    repo_url: &str,
-    version: &str,
-    install_location: &str,
-) -> Result<StateEntry> {
-    // Ensure install directory exists.
-    fs::create_dir_all(install_location)?;
+/// Refuses to install a package over files owned by another package, or by no package, unless
+/// overwriting is allowed.
+pub fn check_conflicts(
+    state: &State,
+    name: &str,
+    paths: &[PathBuf],
+    overwrite: bool,
+) -> Result<()> {
+    let owners = state.owners();
+    let mut conflicts = Vec::new();
+    for path in paths.iter() {
+        match owners.get(path.to_string_lossy().as_ref()) {
+            Some(owner) if *owner == name => {}
+            Some(owner) => conflicts.push(format!("{} belongs to [{}]", path.display(), owner)),
+            None if path.exists() => {
+                conflicts.push(format!("{} is not managed by binman", path.display()))
+            }
+            None => {}
+        }
+    }
 
-    // Create temp dir for asset retrieval.
-    let temp_dir = tempdir()?;
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    if overwrite {
+        for conflict in conflicts.iter() {
+            tracing::warn!("overwriting file: {}", conflict);
+        }
+        return Ok(());
+    }
+    bail!(
+        "Installing [{}] would overwrite existing files - use --overwrite to replace them:\n  {}",
+        name,
+        conflicts.join("\n  ")
+    )
+}
+
+/// A release that was downloaded, verified and extracted to a temporary directory,
+/// but not yet moved to the install location.
+pub struct StagedRelease {
+    temp_dir: TempDir,
+    files: Vec<(PathBuf, PathBuf)>,
+    resolution: Resolution,
+    revision: Option<String>,
+}
 
-    let client = Client::new()?;
-    let repo = client.get_repository(repo_url)?;
-    tracing::info!("starting install");
+impl StagedRelease {
+    /// Swaps the staged files into the install location, replacing the previous artifacts.
+    ///
+    /// The swap must be committed once the state was updated, otherwise it is rolled back.
+    pub fn swap_into(&self, install_location: &Path, previous: &[String]) -> Result<ArtifactSwap> {
+        fs::create_dir_all(install_location)?;
 
-    let maybe_release = if version == "latest" {
-        Some(client.latest_release(&repo).await?)
-    } else {
-        let releases = client.get_releases(&repo).await?;
-        let semv = parse_version_fuzzy(version)?;
-        releases
+        let files = self
+            .files
             .iter()
-            .find(|release| release.version() == semv)
-            .cloned()
+            .map(|(src, file_name)| (src.clone(), install_location.join(file_name)))
+            .collect::<Vec<_>>();
+        let previous = previous.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        let swap = ArtifactSwap::apply(&files, &previous)?;
+        tracing::debug!(staging=?self.temp_dir.path(), "swapped staged assets");
+        Ok(swap)
+    }
+
+    /// The paths the staged files would be installed to.
+    pub fn destinations(&self, install_location: &Path) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .map(|(_, file_name)| install_location.join(file_name))
+            .collect()
+    }
+
+    /// The state entry of the release once swapped in, following the stable channel without requirement.
+    pub fn entry(&self, name: &str, swap: &ArtifactSwap) -> Result<StateEntry> {
+        let mut artifacts = Vec::new();
+        let mut checksums = HashMap::new();
+        for path in swap.installed().iter() {
+            let artifact = String::from(path.to_str().unwrap());
+            checksums.insert(artifact.clone(), sha256_file(path)?);
+            artifacts.push(artifact);
+        }
+
+        Ok(StateEntry {
+            name: String::from(name),
+            url: self.resolution.url.clone(),
+            version: self.resolution.version.clone(),
+            artifacts,
+            checksums,
+            requirement: None,
+            channel: Channel::Stable,
+            revision: self.revision.clone(),
+            tag_pattern: None,
+        })
+    }
+
+    pub fn resolution(&self) -> &Resolution {
+        &self.resolution
+    }
+}
+
+/// Lists the releases of a package, ignoring those with tags of other components.
+///
+/// Listing stops at the page holding a tag satisfying `stop`.
+async fn list_package_releases(
+    source: &dyn ReleaseSource,
+    repo: &Repository,
+    tag_pattern: Option<&TagPattern>,
+    stop: &(dyn Fn(&str) -> bool + Send + Sync),
+) -> Result<Vec<Release>> {
+    let is_component = |tag: &str| tag_pattern.is_none_or(|p| p.is_match(tag));
+    let releases = source
+        .list_releases(repo, &|release| {
+            is_component(release.tag_name()) && stop(release.tag_name())
+        })
+        .await?;
+
+    Ok(releases
+        .into_iter()
+        .filter(|release| is_component(release.tag_name()))
+        .map(|release| release.with_tag_pattern(tag_pattern.cloned()))
+        .collect())
+}
+
+/// Finds the release with a tag, reading its version with a tag pattern.
+pub async fn find_tagged_release(
+    source: &dyn ReleaseSource,
+    repo: &Repository,
+    tag: &str,
+    tag_pattern: Option<&TagPattern>,
+) -> Result<Release> {
+    let releases = list_package_releases(source, repo, None, &|t| t == tag).await?;
+    releases
+        .into_iter()
+        .find(|release| release.tag_name() == tag)
+        .map(|release| release.with_tag_pattern(tag_pattern.cloned()))
+        .ok_or_else(|| anyhow!("Tag {} not found", tag))
+}
+
+/// Finds the release matching a version on a channel.
+///
+/// Nightly channels ignore the version and always resolve to the release of their tag.
+pub async fn find_release(
+    source: &dyn ReleaseSource,
+    repo: &Repository,
+    version: &VersionSpec,
+    channel: &Channel,
+    tag_pattern: Option<&TagPattern>,
+) -> Result<Release> {
+    let fetch_releases = || list_package_releases(source, repo, tag_pattern, &|_| false);
+
+    if let Channel::Nightly(tag) = channel {
+        return find_tagged_release(source, repo, tag, None).await;
+    }
+
+    let include_prereleases = *channel == Channel::Prerelease;
+    let maybe_release = match version {
+        // The host's notion of the latest release ignores tag patterns, as well as other components.
+        VersionSpec::Latest if tag_pattern.is_some() => {
+            Release::latest(fetch_releases().await?, include_prereleases)
+        }
+        VersionSpec::Latest => Some(source.latest_release(repo, include_prereleases).await?),
+        VersionSpec::Exact(semv) => {
+            let is_version = |tag: &str| {
+                parse_tag(tag, tag_pattern)
+                    .map(|v| v == *semv)
+                    .unwrap_or(false)
+            };
+            let releases = list_package_releases(source, repo, tag_pattern, &is_version).await?;
+            releases
+                .into_iter()
+                .find(|release| release.version() == semv)
+        }
+        VersionSpec::Requirement(_) => {
+            let releases = fetch_releases().await?;
+            releases
+                .into_iter()
+                .filter(|release| {
+                    !release.is_draft()
+                        && (include_prereleases || !release.is_prerelease())
+                        && version.matches(release.version(), include_prereleases)
+                })
+                .max_by(|a, b| a.version().cmp(b.version()))
+        }
     };
 
-    ensure!(maybe_release.is_some(), "Version {} not found", version);
+    maybe_release.ok_or_else(|| anyhow!("Version {} not found", version))
+}
+
+/// Downloads, verifies and extracts a release to a temporary directory.
+///
+/// Nothing is written to the install location.
+pub async fn stage_release(
+    source: &dyn ReleaseSource,
+    repo_url: &str,
+    release: &Release,
+    options: &InstallOptions,
+) -> Result<StagedRelease> {
+    // Create temp dir for asset retrieval.
+    let temp_dir = tempdir()?;
+    let client = source.http_client();
+
+    // Moving tags keep their asset URLs, so their downloads are only reused by digest unless offline.
+    let moving = matches!(options.channel, Some(Channel::Nightly(_)));
+    let key = CacheKey {
+        sha256: None,
+        revision: release.revision(),
+        by_url: options.offline || !moving,
+    };
 
-    let release = maybe_release.unwrap();
+    let mut checksums = Checksums::default();
+    let checksum_assets = release.checksum_assets();
+    let checksum_files = try_join_all(
+        checksum_assets
+            .iter()
+            .map(|asset| fetch_text(client, asset, key, options)),
+    )
+    .await?;
+    for (checksum_asset, text) in checksum_assets.iter().zip(checksum_files) {
+        checksums.parse(&text, None);
+        tracing::debug!(asset=%checksum_asset.file_name(), "fetched checksums");
+    }
 
-    let assets = release.platform_assets();
+    let (sidecars, assets): (Vec<&Asset>, Vec<&Asset>) = release
+        .platform_assets()
+        .into_iter()
+        .filter(|asset| !checksum::is_aggregated_checksum_file(asset.file_name()))
+        .partition(|asset| checksum::is_sidecar_checksum_file(asset.file_name()));
 
     ensure!(!assets.is_empty(), "No assets found for current platform");
 
-    for asset in assets.iter() {
-        // TODO: Put back prompt here
-        save_asset(asset, temp_dir.path()).await?;
-        tracing::info!(asset=%asset.name(), "installed asset");
+    if let Some(pin) = &options.pinned {
+        let mut selected = assets.iter().map(|a| a.file_name()).collect::<Vec<_>>();
+        let mut pinned = pin
+            .assets
+            .iter()
+            .map(|a| a.file_name.as_str())
+            .collect::<Vec<_>>();
+        selected.sort_unstable();
+        pinned.sort_unstable();
+        ensure!(
+            selected == pinned,
+            "Selected assets {:?} differ from the pinned assets {:?}",
+            selected,
+            pinned
+        );
+        for asset in assets.iter() {
+            let pinned_url = &pin.asset(asset.file_name())?.download_url;
+            ensure!(
+                asset.download_url == *pinned_url,
+                "Asset {} is served from {} instead of the pinned {}",
+                asset.file_name(),
+                asset.download_url,
+                pinned_url
+            );
+        }
     }
 
-    let asset_paths = move_assets(temp_dir.path(), Path::new(install_location))?;
-    tracing::info!("installation complete");
-    Ok(StateEntry {
-        name: repo.name.clone(),
-        url: String::from(repo_url),
-        version: release.version(),
-        artifacts: asset_paths,
+    let sidecar_files = try_join_all(
+        sidecars
+            .iter()
+            .map(|sidecar| fetch_text(client, sidecar, key, options)),
+    )
+    .await?;
+    for (sidecar, text) in sidecars.iter().zip(sidecar_files) {
+        let target_name = &sidecar.file_name()[..sidecar.file_name().len() - ".sha256".len()];
+        checksums.parse(&text, Some(target_name));
+    }
+
+    // Assets download concurrently, within the limit shared with other packages.
+    // TODO: Put back prompt here
+    let resolved_assets = try_join_all(assets.iter().map(|asset| async {
+        let resolved = save_asset(client, asset, temp_dir.path(), &checksums, key, options).await?;
+        tracing::info!(asset=%asset.name(), "downloaded asset");
+        Ok::<_, anyhow::Error>(resolved)
+    }))
+    .await?;
+
+    let files = stage_assets(temp_dir.path())?;
+    ensure!(!files.is_empty(), "No executable found in release assets");
+
+    Ok(StagedRelease {
+        temp_dir,
+        files,
+        resolution: Resolution {
+            url: String::from(repo_url),
+            tag: String::from(release.tag_name()),
+            version: release.version().clone(),
+            assets: resolved_assets,
+        },
+        revision: release.revision().map(String::from),
     })
 }
 
-#[tracing::instrument(skip(optional_dir_override))]
+#[tracing::instrument(skip(optional_dir_override, options))]
 pub async fn install_target(
     repo_url: &str,
     version: &str,
     optional_dir_override: Option<&String>,
-) -> Result<()> {
+    options: &InstallOptions,
+) -> Result<Resolution> {
     let cfg = Config::new()?;
+    let options = configure(&cfg, options);
     let mut state = State::new(&cfg.state_file_path)?;
 
     let mut used_url = String::from(repo_url);
 
     if !used_url.contains('/') {
         tracing::warn!(default=%cfg.default_code_host, "URL not recognized - falling back on default code host");
-        used_url = vec![cfg.default_code_host.clone(), String::from(repo_url)].join("/");
+        used_url = [cfg.default_code_host.clone(), String::from(repo_url)].join("/");
     }
 
-    let app_name = &Repository::from_url(&used_url)?.name;
+    let repo = Repository::from_url(&used_url)?;
+    let source = source::for_repository(&cfg, &repo, options.offline)?;
+    let name = options.name.as_deref().unwrap_or(&repo.name);
 
     ensure!(
-        state.get(app_name).is_none(),
+        state.get(name).is_none(),
         "Target [{}] is already installed",
-        app_name
+        name
     );
 
     let install_dir = if let Some(overr) = optional_dir_override {
@@ -211,10 +751,116 @@ pub async fn install_target(
         &cfg.install_location
     };
 
-    let new_entry = async_install(&used_url, version, install_dir).await?;
+    let version = VersionSpec::parse(version)?;
+    let requirement = match &version {
+        VersionSpec::Requirement(req) => Some(req.clone()),
+        _ => None,
+    };
 
-    // Insert installation in state.
-    state.insert(new_entry)?;
+    let channel = options.channel.clone().unwrap_or_default();
+
+    tracing::info!(channel=%channel, "starting install");
+    let release = match &options.pinned {
+        Some(pin) => {
+            find_tagged_release(
+                source.as_ref(),
+                &repo,
+                &pin.tag,
+                options.tag_pattern.as_ref(),
+            )
+            .await?
+        }
+        None => {
+            find_release(
+                source.as_ref(),
+                &repo,
+                &version,
+                &channel,
+                options.tag_pattern.as_ref(),
+            )
+            .await?
+        }
+    };
+    let staged = stage_release(source.as_ref(), &used_url, &release, &options).await?;
+    let install_dir = Path::new(install_dir);
+    check_conflicts(
+        &state,
+        name,
+        &staged.destinations(install_dir),
+        options.overwrite,
+    )?;
+    let swap = staged.swap_into(install_dir, &[])?;
+
+    // Insert installation in state, then make the new files permanent.
+    let mut entry = staged.entry(name, &swap)?;
+    entry.requirement = requirement;
+    entry.channel = channel;
+    entry.tag_pattern = options.tag_pattern;
+    state.insert(entry)?;
+    swap.commit()?;
+    tracing::info!("installation complete");
 
-    Ok(())
+    Ok(staged.resolution().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    /// A state in a temporary directory where `owner` installed `owned`, next to an untracked file.
+    fn state_with_files() -> (TempDir, State, PathBuf, PathBuf) {
+        let dir = tempdir().unwrap();
+        let owned = dir.path().join("owned");
+        let untracked = dir.path().join("untracked");
+        fs::write(&owned, "").unwrap();
+        fs::write(&untracked, "").unwrap();
+
+        let mut state = State::new(dir.path().join("state.json").to_str().unwrap()).unwrap();
+        state
+            .insert(StateEntry {
+                name: String::from("owner"),
+                artifacts: vec![String::from(owned.to_str().unwrap())],
+                url: String::from("https://github.com/o/owner"),
+                version: Version::new(1, 0, 0),
+                checksums: HashMap::new(),
+                requirement: None,
+                channel: Channel::Stable,
+                revision: None,
+                tag_pattern: None,
+            })
+            .unwrap();
+        (dir, state, owned, untracked)
+    }
+
+    #[test]
+    fn refuses_files_of_another_package() {
+        let (_dir, state, owned, _) = state_with_files();
+        let err = check_conflicts(&state, "other", &[owned], false)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("belongs to [owner]"), "{}", err);
+    }
+
+    #[test]
+    fn refuses_untracked_files() {
+        let (_dir, state, _, untracked) = state_with_files();
+        let err = check_conflicts(&state, "other", &[untracked], false)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("is not managed by binman"), "{}", err);
+    }
+
+    #[test]
+    fn overwrites_conflicting_files_on_request() {
+        let (_dir, state, owned, untracked) = state_with_files();
+        check_conflicts(&state, "other", &[owned, untracked], true).unwrap();
+    }
+
+    #[test]
+    fn accepts_own_and_new_files() {
+        let (dir, state, owned, _) = state_with_files();
+        check_conflicts(&state, "owner", &[owned, dir.path().join("new")], false).unwrap();
+    }
 }
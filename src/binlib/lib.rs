@@ -1,14 +1,38 @@
+mod cache;
+mod channel;
+mod checksum;
 mod config;
 pub mod fuzzy_semver;
 mod github;
+mod gitlab;
+mod http;
 mod install;
+mod lockfile;
+mod manifest;
+mod progress;
+mod source;
 mod state;
+mod sync;
+mod transaction;
 mod uninstall;
 mod update;
+mod verify;
+mod version_spec;
 mod zip;
 
-pub use config::Config;
-pub use install::install_target;
+pub use cache::{Cache, CacheEntry, PruneSummary};
+pub use channel::Channel;
+pub use config::{ApiFlavor, Config, HostConfig};
+pub use http::DownloadLimit;
+pub use install::{install_target, InstallOptions, Pin, Resolution, ResolvedAsset};
+pub use lockfile::{LockedPackage, Lockfile};
+pub use manifest::{Manifest, ManifestPackage};
+pub use progress::{DownloadEvent, ProgressHandler};
 pub use state::{State, StateEntry};
+pub use sync::sync_manifest;
 pub use uninstall::uninstall_target;
-pub use update::update_target;
+pub use update::{
+    check_updates, update_all, update_target, update_target_to, update_targets, UpdateCheck,
+};
+pub use verify::{verify_artifacts, ArtifactStatus, Discrepancy};
+pub use version_spec::VersionSpec;
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use semver::Version;
+
+use serde::{Deserialize, Serialize};
+
+use tempfile::NamedTempFile;
+
+use crate::install::{Pin, Resolution, ResolvedAsset};
+
+/// The exact release and assets a package is pinned to.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockedPackage {
+    pub url: String,
+    pub tag: String,
+    pub version: Version,
+    pub assets: Vec<ResolvedAsset>,
+}
+
+impl LockedPackage {
+    /// Pins an install to the locked tag and assets.
+    pub fn pin(&self) -> Pin {
+        Pin {
+            tag: self.tag.clone(),
+            assets: self.assets.clone(),
+        }
+    }
+}
+
+impl From<Resolution> for LockedPackage {
+    fn from(resolution: Resolution) -> LockedPackage {
+        LockedPackage {
+            url: resolution.url,
+            tag: resolution.tag,
+            version: resolution.version,
+            assets: resolution.assets,
+        }
+    }
+}
+
+/// Resolved releases of the packages of a manifest, usually stored as `binman.lock`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub packages: BTreeMap<String, LockedPackage>,
+}
+
+impl Lockfile {
+    /// The lockfile accompanying a manifest.
+    pub fn path_for(manifest_path: &Path) -> PathBuf {
+        manifest_path.with_extension("lock")
+    }
+
+    /// Loads a lockfile, which is empty if it does not exist yet.
+    pub fn load(path: &Path) -> Result<Lockfile> {
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("failed to read lockfile {}", path.display()))?;
+        toml::from_str(&raw).with_context(|| format!("failed to parse lockfile {}", path.display()))
+    }
+
+    /// Writes the lockfile to a temporary sibling then renames it over the lockfile, so that a crash
+    /// leaves either the previous or the new lockfile.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut tmp = NamedTempFile::new_in(dir)?;
+        tmp.write_all(toml::to_string_pretty(self)?.as_bytes())?;
+        tmp.as_file().sync_all()?;
+        tmp.persist(path)
+            .with_context(|| format!("failed to replace lockfile {}", path.display()))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+
+use serde::{Deserialize, Serialize};
+
+use shellexpand::tilde;
+
+use crate::fuzzy_semver::TagPattern;
+use crate::source::Repository;
+use crate::Channel;
+
+fn default_version() -> String {
+    String::from("latest")
+}
+
+/// A package declared in a manifest.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestPackage {
+    /// The repository URL, defaulting to the package name on the default code host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// Either `latest`, a specific version or a requirement such as `^1.4`.
+    #[serde(default = "default_version")]
+    pub version: String,
+
+    /// The installation directory, overriding the configured one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+
+    /// Whether to refuse installing assets for which the release publishes no checksum.
+    #[serde(default)]
+    pub require_checksum: bool,
+
+    /// The release channel to follow, stable unless set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Channel>,
+
+    /// A regex locating the version in release tags, within a `version` or first capture group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_pattern: Option<TagPattern>,
+
+    /// Only consider releases tagged with this prefix followed by a version, e.g. `cli-v`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_prefix: Option<String>,
+}
+
+/// A declarative list of packages, usually stored as `binman.toml`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub packages: BTreeMap<String, ManifestPackage>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Manifest> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("failed to read manifest {}", path.display()))?;
+        let mut manifest: Manifest = toml::from_str(&raw)
+            .with_context(|| format!("failed to parse manifest {}", path.display()))?;
+
+        for (name, package) in manifest.packages.iter_mut() {
+            package.dir = package.dir.as_ref().map(|d| tilde(d).to_string());
+            if let Some(prefix) = &package.tag_prefix {
+                ensure!(
+                    package.tag_pattern.is_none(),
+                    "Package [{}] cannot have both a tag prefix and a tag pattern",
+                    name
+                );
+                package.tag_pattern = Some(TagPattern::from_prefix(prefix));
+            }
+
+            // Components of a monorepo are told apart by their tags instead.
+            if let (Some(url), None) = (&package.url, &package.tag_pattern) {
+                let repo = Repository::from_url(url)?;
+                ensure!(
+                    &repo.name == name,
+                    "Package [{}] does not match the name of its repository ({})",
+                    name,
+                    repo.name
+                );
+            }
+        }
+
+        Ok(manifest)
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// Progress of an asset download.
+#[derive(Clone, Debug)]
+pub enum DownloadEvent {
+    Started {
+        asset: String,
+    },
+
+    /// More bytes were received. The count may go back down when a download starts over.
+    Progress {
+        asset: String,
+        downloaded: u64,
+        total: Option<u64>,
+    },
+
+    Finished {
+        asset: String,
+    },
+}
+
+/// Receives download events, e.g. to display progress.
+#[derive(Clone)]
+pub struct ProgressHandler(Arc<dyn Fn(&DownloadEvent) + Send + Sync>);
+
+impl ProgressHandler {
+    pub fn new<F>(handler: F) -> ProgressHandler
+    where
+        F: Fn(&DownloadEvent) + Send + Sync + 'static,
+    {
+        ProgressHandler(Arc::new(handler))
+    }
+
+    pub fn emit(&self, event: DownloadEvent) {
+        (self.0)(&event)
+    }
+}
+
+impl fmt::Debug for ProgressHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressHandler")
+    }
+}
@@ -4,10 +4,10 @@ use regex::Regex;
 
 use rood::sys::{Architecture, Platform};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 fn parse_architecture(name: &str) -> Architecture {
-    let archs = vec![Architecture::Amd64, Architecture::Arm, Architecture::Arm64];
+    let archs = [Architecture::Amd64, Architecture::Arm, Architecture::Arm64];
 
     for arc in archs.iter() {
         for v in arc.value().iter() {
@@ -22,7 +22,7 @@ fn parse_architecture(name: &str) -> Architecture {
 }
 
 fn parse_platform(name: &str) -> Platform {
-    let plats = vec![Platform::Linux, Platform::Darwin, Platform::Windows];
+    let plats = [Platform::Linux, Platform::Darwin, Platform::Windows];
 
     for plat in plats.iter() {
         for plat_value in plat.value().iter() {
@@ -35,10 +35,10 @@ fn parse_platform(name: &str) -> Platform {
     Platform::Unknown
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Asset {
     name: String,
-    pub browser_download_url: String,
+    pub download_url: String,
 }
 
 impl Debug for Asset {
@@ -48,6 +48,13 @@ impl Debug for Asset {
 }
 
 impl Asset {
+    pub fn new(name: &str, download_url: &str) -> Asset {
+        Asset {
+            name: String::from(name),
+            download_url: String::from(download_url),
+        }
+    }
+
     fn strip_extension(&self) -> &str {
         self.name.split('.').next().unwrap()
     }
@@ -56,10 +63,12 @@ impl Asset {
         if !self.name.contains('.') {
             return "";
         }
-        match self.name.split('.').last() {
-            Some(v) => v,
-            None => "",
-        }
+        self.name.split('.').next_back().unwrap_or_default()
+    }
+
+    /// The full file name of the asset.
+    pub fn file_name(&self) -> &str {
+        &self.name
     }
 
     pub fn name(&self) -> &str {
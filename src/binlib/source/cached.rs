@@ -0,0 +1,65 @@
+use anyhow::{anyhow, Result};
+
+use async_trait::async_trait;
+
+use super::{Release, ReleaseSource, Repository, StopCondition};
+use crate::cache::Cache;
+use crate::http::HttpClient;
+
+/// Records the releases listed by a source, and lists them from that record when offline.
+pub struct CachedSource {
+    inner: Box<dyn ReleaseSource>,
+    cache: Cache,
+    offline: bool,
+}
+
+impl CachedSource {
+    pub fn new(inner: Box<dyn ReleaseSource>, cache: Cache, offline: bool) -> CachedSource {
+        CachedSource {
+            inner,
+            cache,
+            offline,
+        }
+    }
+
+    fn key(repo: &Repository) -> String {
+        format!("{}/{}", repo.host, repo.path())
+    }
+}
+
+#[async_trait]
+impl ReleaseSource for CachedSource {
+    async fn list_releases(
+        &self,
+        repo: &Repository,
+        stop: StopCondition<'_>,
+    ) -> Result<Vec<Release>> {
+        if self.offline {
+            return self.cache.releases(&Self::key(repo));
+        }
+
+        let releases = self.inner.list_releases(repo, stop).await?;
+        self.cache.record_releases(&Self::key(repo), &releases)?;
+        Ok(releases)
+    }
+
+    async fn latest_release(
+        &self,
+        repo: &Repository,
+        include_prereleases: bool,
+    ) -> Result<Release> {
+        if self.offline {
+            return Release::latest(self.cache.releases(&Self::key(repo))?, include_prereleases)
+                .ok_or_else(|| anyhow!("No cached release found"));
+        }
+
+        let release = self.inner.latest_release(repo, include_prereleases).await?;
+        self.cache
+            .record_releases(&Self::key(repo), std::slice::from_ref(&release))?;
+        Ok(release)
+    }
+
+    fn http_client(&self) -> &HttpClient {
+        self.inner.http_client()
+    }
+}
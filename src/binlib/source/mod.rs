@@ -0,0 +1,248 @@
+mod asset;
+mod cached;
+mod release;
+mod repository;
+
+use std::future::Future;
+
+use anyhow::Result;
+
+use async_trait::async_trait;
+
+use reqwest::header;
+
+pub use asset::Asset;
+use cached::CachedSource;
+pub use release::Release;
+pub use repository::Repository;
+
+use crate::cache::Cache;
+use crate::http::HttpClient;
+use crate::{github, gitlab, ApiFlavor, Config};
+
+/// Releases requested per page of the releases APIs.
+pub const RELEASES_PER_PAGE: usize = 100;
+
+/// Most pages of releases fetched for a repository.
+const MAX_RELEASE_PAGES: usize = 10;
+
+/// Tells whether enough releases were listed once a page holds a given release.
+pub type StopCondition<'a> = &'a (dyn Fn(&Release) -> bool + Send + Sync);
+
+/// A service hosting repositories and their releases.
+#[async_trait]
+pub trait ReleaseSource: Send + Sync {
+    /// Lists the releases of a repository, most recent first.
+    ///
+    /// Pages are fetched until one holds a release satisfying `stop`, up to a cap.
+    async fn list_releases(
+        &self,
+        repo: &Repository,
+        stop: StopCondition<'_>,
+    ) -> Result<Vec<Release>>;
+
+    /// Lists all the releases of a repository, most recent first, up to a cap.
+    async fn get_releases(&self, repo: &Repository) -> Result<Vec<Release>> {
+        self.list_releases(repo, &|_| false).await
+    }
+
+    /// Gets the latest release of a repository, which is stable unless prereleases are included.
+    async fn latest_release(&self, repo: &Repository, include_prereleases: bool)
+        -> Result<Release>;
+
+    /// The HTTP client configured for the host, used to download assets.
+    fn http_client(&self) -> &HttpClient;
+}
+
+/// Connection settings of the API of a code host.
+#[derive(Debug)]
+pub struct Endpoint {
+    /// The host of the repositories, which may differ from the host of the API.
+    pub host: String,
+    pub api_url: String,
+    pub token: Option<String>,
+    pub ca_cert: Option<String>,
+}
+
+impl Endpoint {
+    fn new(cfg: &Config, host: &str, flavor: ApiFlavor) -> Endpoint {
+        let host_cfg = cfg.host_config(host);
+        let api_url = host_cfg.api_url.unwrap_or_else(|| match flavor {
+            ApiFlavor::Github if host == "github.com" => String::from("https://api.github.com"),
+            ApiFlavor::Github => format!("https://{}/api/v3", host),
+            ApiFlavor::Gitlab => format!("https://{}/api/v4", host),
+            ApiFlavor::Gitea | ApiFlavor::Forgejo => format!("https://{}/api/v1", host),
+        });
+
+        Endpoint {
+            host: String::from(host),
+            api_url: String::from(api_url.trim_end_matches('/')),
+            token: cfg.token_for(host),
+            ca_cert: host_cfg.ca_cert,
+        }
+    }
+}
+
+/// The URL of the next page of a paginated response, from its `Link` header.
+pub fn next_page_url(headers: &header::HeaderMap) -> Option<String> {
+    let links = headers.get(header::LINK)?.to_str().ok()?;
+    links.split(',').find_map(|link| {
+        let mut parts = link.split(';').map(str::trim);
+        let url = parts.next()?.strip_prefix('<')?.strip_suffix('>')?;
+        parts
+            .any(|param| param == r#"rel="next""#)
+            .then(|| String::from(url))
+    })
+}
+
+/// Collects releases page by page, following the URL of the next page returned with each.
+pub async fn paginate<F, Fut>(
+    first_page_url: String,
+    stop: StopCondition<'_>,
+    fetch_page: F,
+) -> Result<Vec<Release>>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<(Vec<Release>, Option<String>)>>,
+{
+    let mut releases = Vec::new();
+    let mut next_url = Some(first_page_url);
+
+    for page in 1..=MAX_RELEASE_PAGES {
+        let url = match next_url.take() {
+            Some(url) => url,
+            None => break,
+        };
+        let (page_releases, next) = fetch_page(url).await?;
+        let found = page_releases.iter().any(stop);
+        releases.extend(page_releases);
+
+        if found {
+            tracing::debug!(page = page, "found the release, stopping");
+            return Ok(releases);
+        }
+        next_url = next;
+    }
+
+    if next_url.is_some() {
+        tracing::warn!(
+            count = releases.len(),
+            "only the most recent releases were listed"
+        );
+    }
+    Ok(releases)
+}
+
+/// Picks the release source matching the host of a repository.
+///
+/// Listed releases are recorded in the cache, which is the only source used when offline.
+pub fn for_repository(
+    cfg: &Config,
+    repo: &Repository,
+    offline: bool,
+) -> Result<Box<dyn ReleaseSource>> {
+    let flavor = cfg.flavor_for(&repo.host);
+    let endpoint = Endpoint::new(cfg, &repo.host, flavor);
+    tracing::debug!(host=%repo.host, flavor=?flavor, api=%endpoint.api_url, "selected release source");
+
+    let source: Box<dyn ReleaseSource> = match flavor {
+        ApiFlavor::Gitlab => Box::new(gitlab::Client::new(&endpoint)?),
+        _ => Box::new(github::Client::new(
+            &endpoint,
+            flavor,
+            cfg.wait_on_rate_limit,
+            cfg.use_latest_endpoint,
+        )?),
+    };
+    Ok(Box::new(CachedSource::new(
+        source,
+        Cache::new(&cfg.cache_dir),
+        offline,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::*;
+
+    fn with_link(link: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::LINK, HeaderValue::from_str(link).unwrap());
+        headers
+    }
+
+    #[test]
+    fn finds_next_page_among_rels() {
+        let headers = with_link(
+            r#"<https://api.github.com/repositories/1/releases?page=1>; rel="prev", <https://api.github.com/repositories/1/releases?page=3>; rel="next", <https://api.github.com/repositories/1/releases?page=9>; rel="last""#,
+        );
+        assert_eq!(
+            next_page_url(&headers).as_deref(),
+            Some("https://api.github.com/repositories/1/releases?page=3")
+        );
+    }
+
+    #[test]
+    fn has_no_next_page_without_link() {
+        assert_eq!(next_page_url(&HeaderMap::new()), None);
+        assert_eq!(
+            next_page_url(&with_link(r#"<https://host/releases?page=1>; rel="first""#)),
+            None
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_links() {
+        for link in [
+            r#"https://host/releases?page=2; rel="next""#,
+            r#"<https://host/releases?page=2; rel="next""#,
+            r#"<https://host/releases?page=2>"#,
+            r#"<https://host/releases?page=2>; rel=next-ish"#,
+            "garbage",
+        ] {
+            assert_eq!(next_page_url(&with_link(link)), None, "{}", link);
+        }
+    }
+
+    /// A page of releases along with the URL of the next one.
+    type Page = Result<(Vec<Release>, Option<String>)>;
+
+    /// Serves endless pages of releases tagged after their page number.
+    fn endless_pages(
+        fetched: &AtomicUsize,
+    ) -> impl Fn(String) -> futures::future::Ready<Page> + '_ {
+        move |_url| {
+            let page = fetched.fetch_add(1, Ordering::SeqCst) + 1;
+            let release = Release::new(&format!("v{}.0.0", page), Vec::new(), false, false, None);
+            futures::future::ready(Ok((vec![release], Some(format!("page={}", page + 1)))))
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_at_page_cap() {
+        let fetched = AtomicUsize::new(0);
+        let releases = paginate(String::from("page=1"), &|_| false, endless_pages(&fetched))
+            .await
+            .unwrap();
+        assert_eq!(fetched.load(Ordering::SeqCst), MAX_RELEASE_PAGES);
+        assert_eq!(releases.len(), MAX_RELEASE_PAGES);
+    }
+
+    #[tokio::test]
+    async fn stops_at_wanted_release() {
+        let fetched = AtomicUsize::new(0);
+        let releases = paginate(
+            String::from("page=1"),
+            &|release| release.tag_name() == "v3.0.0",
+            endless_pages(&fetched),
+        )
+        .await
+        .unwrap();
+        assert_eq!(fetched.load(Ordering::SeqCst), 3);
+        assert_eq!(releases.len(), 3);
+    }
+}
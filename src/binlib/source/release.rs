@@ -0,0 +1,237 @@
+use std::sync::OnceLock;
+
+use rood::sys::{Architecture, Platform};
+
+use semver::{Prerelease, Version};
+
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::is_aggregated_checksum_file;
+use crate::fuzzy_semver::{parse_tag, TagPattern};
+
+use super::Asset;
+
+/// A tag turned into prerelease identifiers, replacing the characters semver forbids with `-`.
+fn tag_prerelease(tag: &str) -> Prerelease {
+    let identifiers = tag
+        .split('.')
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            let id = id
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect::<String>();
+            // Numeric identifiers may not have leading zeros.
+            match id.parse::<u64>() {
+                Ok(n) => n.to_string(),
+                Err(_) => id,
+            }
+        })
+        .collect::<Vec<_>>();
+    Prerelease::new(&identifiers.join(".")).unwrap_or(Prerelease::EMPTY)
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+    draft: bool,
+    prerelease: bool,
+    revision: Option<String>,
+
+    #[serde(skip)]
+    tag_pattern: Option<TagPattern>,
+
+    /// The version read from the tag, parsed on first use.
+    #[serde(skip)]
+    version: OnceLock<Version>,
+}
+
+impl Release {
+    pub fn new(
+        tag_name: &str,
+        assets: Vec<Asset>,
+        draft: bool,
+        prerelease: bool,
+        revision: Option<String>,
+    ) -> Release {
+        Release {
+            tag_name: String::from(tag_name),
+            assets,
+            draft,
+            prerelease,
+            revision,
+            tag_pattern: None,
+            version: OnceLock::new(),
+        }
+    }
+
+    /// Reads the version of the release with a tag pattern.
+    pub fn with_tag_pattern(mut self, tag_pattern: Option<TagPattern>) -> Release {
+        self.tag_pattern = tag_pattern;
+        self.version = OnceLock::new();
+        self
+    }
+
+    /// Picks the highest version among releases, ignoring drafts, and prereleases unless included.
+    ///
+    /// Hosts list releases by creation date, which puts backports of older lines first.
+    pub fn latest(releases: Vec<Release>, include_prereleases: bool) -> Option<Release> {
+        releases
+            .into_iter()
+            .filter(|release| {
+                !release.is_draft() && (include_prereleases || !release.is_prerelease())
+            })
+            .max_by(|a, b| a.version().cmp(b.version()))
+    }
+
+    /// Identifies the contents of the release, which changes when a moving tag is rebuilt.
+    pub fn revision(&self) -> Option<&str> {
+        self.revision.as_deref()
+    }
+
+    pub fn is_draft(&self) -> bool {
+        self.draft
+    }
+
+    /// Whether the release is flagged as a prerelease, or its version has a prerelease component.
+    pub fn is_prerelease(&self) -> bool {
+        self.prerelease || !self.version().pre.is_empty()
+    }
+
+    pub fn tag_name(&self) -> &str {
+        &self.tag_name
+    }
+
+    /// The version of the release, or `0.0.0-<tag>` when the tag holds none.
+    pub fn version(&self) -> &Version {
+        self.version.get_or_init(|| {
+            parse_tag(&self.tag_name, self.tag_pattern.as_ref()).unwrap_or_else(|_| {
+                let mut v = Version::new(0, 0, 0);
+                v.pre = tag_prerelease(&self.tag_name);
+                v
+            })
+        })
+    }
+
+    pub fn platform_assets(&self) -> Vec<&Asset> {
+        let cur_platform = Platform::detect();
+        let cur_arch = Architecture::detect();
+
+        // Hack to support Apple Silicon + Rosetta
+        let fallback_architectures =
+            if cur_platform == Platform::Darwin && cur_arch == Architecture::Arm64 {
+                vec![Architecture::Arm64, Architecture::Amd64]
+            } else {
+                vec![cur_arch]
+            };
+
+        for arch in fallback_architectures {
+            let arch_assets = self
+                .assets
+                .iter()
+                .filter(|asset| asset.platform() == cur_platform && asset.architecture() == arch)
+                .collect::<Vec<_>>();
+
+            if !arch_assets.is_empty() {
+                return arch_assets;
+            }
+        }
+
+        Vec::default()
+    }
+
+    /// Checksums files covering several assets of the release.
+    pub fn checksum_assets(&self) -> Vec<&Asset> {
+        self.assets
+            .iter()
+            .filter(|asset| is_aggregated_checksum_file(asset.file_name()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(tag: &str) -> Release {
+        Release::new(tag, Vec::new(), false, false, None)
+    }
+
+    fn latest_tag(releases: Vec<Release>, include_prereleases: bool) -> String {
+        String::from(
+            Release::latest(releases, include_prereleases)
+                .unwrap()
+                .tag_name(),
+        )
+    }
+
+    #[test]
+    fn latest_ignores_listing_order() {
+        // Backports are published after the release they precede.
+        let releases = vec![release("v1.4.9"), release("v2.1.0"), release("v2.0.3")];
+        assert_eq!(latest_tag(releases, false), "v2.1.0");
+    }
+
+    #[test]
+    fn latest_ignores_drafts() {
+        let releases = vec![
+            Release::new("v3.0.0", Vec::new(), true, false, None),
+            release("v2.1.0"),
+        ];
+        assert_eq!(latest_tag(releases.clone(), false), "v2.1.0");
+        assert_eq!(latest_tag(releases, true), "v2.1.0");
+    }
+
+    #[test]
+    fn latest_includes_prereleases_on_request() {
+        let releases = vec![
+            Release::new("v2.2.0", Vec::new(), false, true, None),
+            release("v2.1.0"),
+            release("v1.4.9"),
+        ];
+        assert_eq!(latest_tag(releases.clone(), false), "v2.1.0");
+        assert_eq!(latest_tag(releases, true), "v2.2.0");
+
+        let releases = vec![release("v2.2.0-rc.1"), release("v2.1.0")];
+        assert_eq!(latest_tag(releases.clone(), false), "v2.1.0");
+        assert_eq!(latest_tag(releases, true), "v2.2.0-rc.1");
+    }
+
+    #[test]
+    fn latest_of_prereleases_only() {
+        let releases = vec![release("v1.0.0-rc.1")];
+        assert!(Release::latest(releases.clone(), false).is_none());
+        assert_eq!(latest_tag(releases, true), "v1.0.0-rc.1");
+    }
+
+    #[test]
+    fn versions_tags_without_one() {
+        for tag in [
+            "nightly_build",
+            "latest+x/y",
+            "v2.0.0_rc1",
+            "a..b",
+            "nightly.01",
+        ] {
+            let release = release(tag);
+            let version = release.version();
+            assert_eq!((version.major, version.minor, version.patch), (0, 0, 0));
+            assert!(!version.pre.is_empty(), "{}", tag);
+        }
+        assert_eq!(
+            release("nightly_build").version().to_string(),
+            "0.0.0-nightly-build"
+        );
+    }
+
+    #[test]
+    fn rereads_version_with_tag_pattern() {
+        let release = release("cli-v1.2.3-build.4");
+        assert_eq!(release.version().to_string(), "1.2.3-build.4");
+
+        let pattern = TagPattern::new(r"^cli-v(\d+\.\d+\.\d+)").unwrap();
+        let release = release.with_tag_pattern(Some(pattern));
+        assert_eq!(release.version().to_string(), "1.2.3");
+    }
+}
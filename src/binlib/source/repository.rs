@@ -0,0 +1,55 @@
+use anyhow::{ensure, Result};
+
+static DEFAULT_HOST: &str = "github.com";
+
+#[derive(Debug)]
+pub struct Repository {
+    pub host: String,
+    pub name: String,
+    pub owner: String,
+}
+
+impl Repository {
+    pub fn new(host: &str, name: &str, owner: &str) -> Repository {
+        Repository {
+            host: String::from(host),
+            name: String::from(name),
+            owner: String::from(owner),
+        }
+    }
+
+    /// Parses a repository URL of the form `[scheme://][host/]owner/name`.
+    ///
+    /// The owner may contain several segments (e.g. GitLab subgroups).
+    pub fn from_url(url: &str) -> Result<Repository> {
+        let without_scheme = url.split_once("://").map(|(_, r)| r).unwrap_or(url);
+        let splitted: Vec<&str> = without_scheme
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .split('/')
+            .collect();
+
+        ensure!(
+            splitted.len() >= 2 && splitted.iter().all(|s| !s.is_empty()),
+            "URL \"{}\" is invalid",
+            url
+        );
+
+        let (host, path) = if splitted.len() == 2 {
+            (DEFAULT_HOST, &splitted[..])
+        } else {
+            (splitted[0], &splitted[1..])
+        };
+
+        Ok(Repository::new(
+            host,
+            path.last().unwrap(),
+            &path[..path.len() - 1].join("/"),
+        ))
+    }
+
+    /// The repository path relative to its host.
+    pub fn path(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+}
@@ -1,25 +1,178 @@
 use std::collections::{HashMap, HashSet};
-use std::fs;
-use std::io::{BufWriter, Write};
-use std::ops::Add;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::{ensure, Result};
+use anyhow::{bail, ensure, Context, Result};
 
-use semver::Version;
+use fs2::FileExt;
+
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 
+use crate::fuzzy_semver::TagPattern;
+use crate::Channel;
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct StateEntry {
     pub name: String,
     pub artifacts: Vec<String>,
     pub url: String,
     pub version: Version,
+
+    /// SHA-256 digests of the artifacts, recorded at install time.
+    #[serde(default)]
+    pub checksums: HashMap<String, String>,
+
+    /// The requirement updates must stay within, if the package was installed with one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requirement: Option<VersionReq>,
+
+    /// The kind of releases the package follows.
+    #[serde(default, skip_serializing_if = "Channel::is_stable")]
+    pub channel: Channel,
+
+    /// The revision of the installed release, used to detect rebuilds of nightly tags.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+
+    /// The pattern release tags must follow, for components of a monorepo.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_pattern: Option<TagPattern>,
+}
+
+/// Version of the state document written by this release.
+const STATE_VERSION: u32 = 1;
+
+/// The state file, versioned so that older formats can be migrated.
+///
+/// Version 0 is the bare map of packages written before the document was versioned.
+#[derive(Deserialize, Serialize)]
+struct StateDocument {
+    version: u32,
+    packages: HashMap<String, StateEntry>,
+}
+
+/// Reads a state document and its version, accepting older formats.
+fn parse_state(contents: &[u8]) -> Result<StateDocument> {
+    let value: serde_json::Value = serde_json::from_slice(contents)?;
+
+    // Packages are objects, so a numeric version cannot be a package named `version`.
+    if value.get("version").is_some_and(|v| v.is_u64()) {
+        Ok(serde_json::from_value(value)?)
+    } else {
+        Ok(StateDocument {
+            version: 0,
+            packages: serde_json::from_value(value)?,
+        })
+    }
+}
+
+/// Copies an unreadable state file aside, returning the path of the copy.
+///
+/// A copy holding the same contents is reused, so that repeated attempts do not pile up.
+fn back_up(path: &str, contents: &[u8]) -> Result<String> {
+    let mut backup = format!("{}.corrupt", path);
+    if let Ok(existing) = fs::read(&backup) {
+        if existing == contents {
+            return Ok(backup);
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        backup = format!("{}.{}.corrupt", path, now);
+    }
+    fs::write(&backup, contents)?;
+    Ok(backup)
+}
+
+/// Longest wait for another binman process to release the state.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delay between attempts to take the state lock.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The process holding a lock file, as recorded in it.
+fn lock_holder(lock: &mut File) -> String {
+    let mut holder = String::new();
+    let _ = lock.seek(SeekFrom::Start(0));
+    let _ = lock.read_to_string(&mut holder);
+    match holder.trim() {
+        "" => String::from("another process"),
+        pid => format!("process {}", pid),
+    }
+}
+
+/// Polls a lock file until it is locked, or [`LOCK_TIMEOUT`] elapsed.
+fn wait_for_lock(lock: &mut File, path: &str) -> Result<()> {
+    let started = Instant::now();
+    while lock.try_lock_exclusive().is_err() {
+        if started.elapsed() >= LOCK_TIMEOUT {
+            bail!(
+                "State is locked by {} - gave up after {}s (lock file: {})",
+                lock_holder(lock),
+                LOCK_TIMEOUT.as_secs(),
+                path
+            );
+        }
+        thread::sleep(LOCK_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Runs a blocking operation, letting the async runtime move its other tasks off the current
+/// worker if there is one.
+fn blocking<T>(operation: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(operation)
+        }
+        _ => operation(),
+    }
+}
+
+/// Takes an exclusive advisory lock on `path`, waiting up to [`LOCK_TIMEOUT`] for it.
+///
+/// The lock is released by the OS when the file is closed, including when the process dies, so
+/// a lock file left behind by a killed process does not block later commands.
+fn acquire_lock(path: &str) -> Result<File> {
+    let mut lock = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        // The PID of the current holder is kept until the lock is taken.
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("failed to open lock file {}", path))?;
+
+    if lock.try_lock_exclusive().is_err() {
+        tracing::info!(
+            "waiting for {} to release the state",
+            lock_holder(&mut lock)
+        );
+        blocking(|| wait_for_lock(&mut lock, path))?;
+    }
+
+    // Record the holder for diagnostics.
+    lock.set_len(0)?;
+    lock.seek(SeekFrom::Start(0))?;
+    write!(lock, "{}", process::id())?;
+    lock.flush()?;
+    Ok(lock)
 }
 
 pub struct State {
     path: String,
     internal_data: HashMap<String, StateEntry>,
+
+    /// The locked file guarding the state, held for the lifetime of the value.
+    lock: File,
 }
 
 impl State {
@@ -27,41 +180,53 @@ impl State {
         let mut s = State {
             path: String::from(path),
             internal_data: HashMap::new(),
+            lock: acquire_lock(&format!("{}.lock", path))?,
         };
-        s.acquire_lock()?;
         s.refresh()?;
 
         Ok(s)
     }
 
-    fn acquire_lock(&self) -> Result<()> {
-        let lock_str = self.path.clone().add(".lock");
-        let lock_path = Path::new(&lock_str);
-
-        ensure!(!lock_path.exists(), "Lock is already acquired");
-
-        let mut file_handle = fs::File::create(lock_path)?;
-        file_handle.write_all(b"lock")?;
-        Ok(())
-    }
-
-    fn release_lock(&self) -> Result<()> {
-        let lock_str = self.path.clone().add(".lock");
-        let lock_path = Path::new(&lock_str);
-
-        ensure!(lock_path.exists(), "Attempted to release a free lock");
-        fs::remove_file(lock_path)?;
-
-        Ok(())
-    }
-
+    /// Loads the state file, which may not exist yet.
+    ///
+    /// An unreadable file is copied aside and reported, as overwriting it would lose the record of
+    /// every installed package.
     fn refresh(&mut self) -> Result<()> {
-        self.internal_data = HashMap::new();
-        if let Ok(contents) = fs::read_to_string(&self.path) {
-            if let Ok(internal_data) = serde_json::from_str(&contents) {
-                self.internal_data = internal_data;
+        let contents = match fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.internal_data = HashMap::new();
+                return Ok(());
             }
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read state file {}", self.path))
+            }
+        };
+
+        let document = match parse_state(&contents) {
+            Ok(document) => document,
+            Err(e) => {
+                let backup = back_up(&self.path, &contents)?;
+                bail!(
+                    "State file {} is unreadable: {:#}\nA copy was saved to {} - repair or remove the state file to continue",
+                    self.path,
+                    e,
+                    backup
+                )
+            }
+        };
+        ensure!(
+            document.version <= STATE_VERSION,
+            "State version {} is newer than the supported version {} - upgrade binman",
+            document.version,
+            STATE_VERSION
+        );
+        if document.version < STATE_VERSION {
+            // Older documents are rewritten in the current format on the next save.
+            tracing::debug!(version = document.version, "migrating state");
         }
+
+        self.internal_data = document.packages;
         Ok(())
     }
 
@@ -74,17 +239,42 @@ impl State {
     }
 
     pub fn list(&self) -> Vec<&StateEntry> {
-        self.internal_data.iter().map(|(_, v)| v).collect()
+        self.internal_data.values().collect()
     }
 
-    pub fn insert(&mut self, mut entry: StateEntry) -> Result<()> {
+    /// Maps every installed artifact to the package owning it.
+    pub fn owners(&self) -> HashMap<&str, &str> {
+        self.internal_data
+            .values()
+            .flat_map(|e| {
+                e.artifacts
+                    .iter()
+                    .map(move |a| (a.as_str(), e.name.as_str()))
+            })
+            .collect()
+    }
+
+    pub fn insert(&mut self, entry: StateEntry) -> Result<()> {
         // Will throw if entry already exists.
         ensure!(
             !self.internal_data.contains_key(&entry.name),
             "Target {} already in state",
             &entry.name
         );
+        self.put(entry)
+    }
+
+    /// Replaces an existing entry in a single write.
+    pub fn replace(&mut self, entry: StateEntry) -> Result<()> {
+        ensure!(
+            self.internal_data.contains_key(&entry.name),
+            "Target {} not in state",
+            &entry.name
+        );
+        self.put(entry)
+    }
 
+    fn put(&mut self, mut entry: StateEntry) -> Result<()> {
         // De-duplicate entry artifacts.
         let mut v = Vec::new();
         let mut hsh = HashSet::new();
@@ -95,13 +285,46 @@ impl State {
             }
         }
         entry.artifacts = v;
+
+        // A file belongs to a single package, the one that installed it last.
+        for other in self.internal_data.values_mut() {
+            if other.name == entry.name {
+                continue;
+            }
+            let before = other.artifacts.len();
+            other.artifacts.retain(|a| !hsh.contains(a));
+            other.checksums.retain(|a, _| !hsh.contains(a));
+            if other.artifacts.len() != before {
+                tracing::warn!(package=%other.name, "files taken over by [{}]", entry.name);
+            }
+        }
+
         self.internal_data.insert(entry.name.clone(), entry);
         self.save()
     }
 
+    /// Writes the state to a temporary sibling then renames it over the state file, so that a crash
+    /// leaves either the previous or the new state.
     fn save(&self) -> Result<()> {
-        let file_handle = fs::File::create(&self.path)?;
-        serde_json::to_writer(BufWriter::new(file_handle), &self.internal_data)?;
+        let tmp_path = format!("{}.tmp", self.path);
+        let document = StateDocument {
+            version: STATE_VERSION,
+            packages: self.internal_data.clone(),
+        };
+
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, &document)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("failed to replace state file {}", self.path))?;
+        // Persist the rename itself.
+        #[cfg(unix)]
+        if let Some(parent) = Path::new(&self.path).parent() {
+            File::open(parent)?.sync_all()?;
+        }
         Ok(())
     }
 
@@ -116,11 +339,128 @@ impl State {
 
 impl Drop for State {
     fn drop(&mut self) {
-        match self.release_lock() {
-            Ok(_) => {}
-            Err(e) => {
-                eprintln!("{}", e);
-            }
+        // The lock file is kept, removing it would let another process lock a stale inode.
+        let _ = self.lock.set_len(0);
+        if let Err(e) = self.lock.unlock() {
+            eprintln!("{}", e);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    fn entry(name: &str, artifacts: &[&str]) -> StateEntry {
+        StateEntry {
+            name: String::from(name),
+            artifacts: artifacts.iter().map(|a| String::from(*a)).collect(),
+            url: format!("https://github.com/o/{}", name),
+            version: Version::new(1, 0, 0),
+            checksums: artifacts
+                .iter()
+                .map(|a| (String::from(*a), String::from("abc")))
+                .collect(),
+            requirement: None,
+            channel: Channel::Stable,
+            revision: None,
+            tag_pattern: None,
+        }
+    }
+
+    /// A directory holding a state file with the given contents.
+    fn state_file(contents: &str) -> (TempDir, String) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        fs::write(&path, contents).unwrap();
+        let path = String::from(path.to_str().unwrap());
+        (dir, path)
+    }
+
+    #[test]
+    fn migrates_bare_map() {
+        let bare = r#"{"tool": {"name": "tool", "artifacts": ["/bin/tool"], "url": "https://github.com/o/tool", "version": "1.2.3"}}"#;
+        let document = parse_state(bare.as_bytes()).unwrap();
+        assert_eq!(document.version, 0);
+        assert_eq!(document.packages["tool"].version, Version::new(1, 2, 3));
+
+        let (_dir, path) = state_file(bare);
+        let mut state = State::new(&path).unwrap();
+        assert_eq!(state.get("tool").unwrap().artifacts, vec!["/bin/tool"]);
+        state.insert(entry("other", &["/bin/other"])).unwrap();
+        drop(state);
+
+        let saved = parse_state(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved.version, STATE_VERSION);
+        assert_eq!(saved.packages.len(), 2);
+    }
+
+    #[test]
+    fn reads_current_version() {
+        let (_dir, path) = state_file(r#"{"version": 1, "packages": {}}"#);
+        let state = State::new(&path).unwrap();
+        assert!(state.list().is_empty());
+    }
+
+    #[test]
+    fn backs_up_corrupt_state() {
+        let (_dir, path) = state_file("{not json");
+
+        let err = format!("{:#}", State::new(&path).err().unwrap());
+        let backup = format!("{}.corrupt", path);
+        assert!(err.contains("is unreadable"), "{}", err);
+        assert!(err.contains(&backup), "{}", err);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "{not json");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{not json");
+
+        // Retrying reuses the copy rather than piling up new ones.
+        assert!(State::new(&path).is_err());
+        let backups = fs::read_dir(Path::new(&path).parent().unwrap())
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".corrupt")
+            })
+            .count();
+        assert_eq!(backups, 1);
+    }
+
+    #[test]
+    fn refuses_newer_version() {
+        let newer = r#"{"version": 99, "packages": {}}"#;
+        let (_dir, path) = state_file(newer);
+
+        let err = State::new(&path).err().unwrap().to_string();
+        assert!(err.contains("newer"), "{}", err);
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+        assert!(!Path::new(&format!("{}.corrupt", path)).exists());
+    }
+
+    #[test]
+    fn moves_ownership_in_the_same_put() {
+        let (dir, path) = state_file(r#"{"version": 1, "packages": {}}"#);
+        let shared = dir.path().join("bin").join("shared");
+        let shared = shared.to_str().unwrap();
+
+        let mut state = State::new(&path).unwrap();
+        state
+            .insert(entry("first", &[shared, "/bin/first"]))
+            .unwrap();
+        state.insert(entry("second", &[shared])).unwrap();
+        assert_eq!(state.owners()[shared], "second");
+        drop(state);
+
+        // The transfer was written along with the new entry.
+        let state = State::new(&path).unwrap();
+        let first = state.get("first").unwrap();
+        assert_eq!(first.artifacts, vec!["/bin/first"]);
+        assert!(!first.checksums.contains_key(shared));
+        assert_eq!(state.get("second").unwrap().artifacts, vec![shared]);
+        assert_eq!(state.owners()[shared], "second");
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+use semver::Version;
+
+use super::install::{InstallOptions, Resolution};
+use super::lockfile::Lockfile;
+use super::manifest::{Manifest, ManifestPackage};
+use super::{install_target, uninstall_target, update_target_to, Config, State, VersionSpec};
+
+/// Installs a package from the exact tag and assets recorded in the lockfile, refusing any other.
+async fn sync_locked(
+    name: &str,
+    package: &ManifestPackage,
+    current: Option<&Version>,
+    lock: &Lockfile,
+    options: &InstallOptions,
+) -> Result<()> {
+    let locked = lock
+        .packages
+        .get(name)
+        .ok_or_else(|| anyhow!("Package [{}] is not in the lockfile", name))?;
+
+    if current == Some(&locked.version) {
+        tracing::debug!(target=%name, "already at locked version");
+        return Ok(());
+    }
+
+    let mut options = options.clone();
+    options.pinned = Some(locked.pin());
+    let locked_version = locked.version.to_string();
+
+    match current {
+        None => {
+            install_target(&locked.url, &locked_version, package.dir.as_ref(), &options).await?;
+        }
+        Some(_) => {
+            update_target_to(name, Some(&locked_version), false, &options).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Installs or updates a package to match the manifest, returning the release it now uses
+/// if it had to be resolved.
+async fn sync_unlocked(
+    name: &str,
+    package: &ManifestPackage,
+    current: Option<&Version>,
+    lock: &Lockfile,
+    options: &InstallOptions,
+) -> Result<Option<Resolution>> {
+    let current = match current {
+        Some(current) => current,
+        None => {
+            let url = package.url.clone().unwrap_or_else(|| String::from(name));
+            tracing::info!(target=%name, version=%package.version, "installing");
+            return Ok(Some(
+                install_target(&url, &package.version, package.dir.as_ref(), options).await?,
+            ));
+        }
+    };
+
+    let lock_is_current = lock
+        .packages
+        .get(name)
+        .map(|l| l.version == *current)
+        .unwrap_or(false);
+
+    let resolution = match VersionSpec::parse(&package.version)? {
+        VersionSpec::Exact(declared) if declared == *current => None,
+        _ => update_target_to(name, Some(&package.version), false, options).await?,
+    };
+
+    if resolution.is_none() && !lock_is_current {
+        // The assets of the installed release must be fetched again to be locked.
+        tracing::info!(target=%name, "resolving installed release for the lockfile");
+        return update_target_to(name, Some(&current.to_string()), true, options).await;
+    }
+
+    Ok(resolution)
+}
+
+/// Brings installed packages in line with a manifest.
+///
+/// Missing packages are installed and installed ones are moved to the declared version, recording
+/// the resolved releases in the lockfile next to the manifest. When `locked` is set, the lockfile
+/// is followed instead and any asset whose digest differs from it is refused.
+/// When `prune` is set, packages absent from the manifest are uninstalled.
+#[tracing::instrument(skip(options))]
+pub async fn sync_manifest(
+    manifest_path: &Path,
+    prune: bool,
+    locked: bool,
+    options: &InstallOptions,
+) -> Result<()> {
+    let cfg = Config::new()?;
+    let manifest = Manifest::load(manifest_path)?;
+    let lock_path = Lockfile::path_for(manifest_path);
+    let mut lock = Lockfile::load(&lock_path)?;
+
+    // Snapshot the installed versions, each operation below takes its own lock on state.
+    let installed: Vec<(String, Version)> = {
+        let state = State::new(&cfg.state_file_path)?;
+        state
+            .list()
+            .into_iter()
+            .map(|e| (e.name.clone(), e.version.clone()))
+            .collect()
+    };
+
+    for (name, package) in manifest.packages.iter() {
+        let mut options = options.clone();
+        options.require_checksum |= package.require_checksum;
+        if package.channel.is_some() {
+            options.channel = package.channel.clone();
+        }
+        if package.tag_pattern.is_some() {
+            options.tag_pattern = package.tag_pattern.clone();
+        }
+        options.name = Some(name.clone());
+        let current = installed.iter().find(|(n, _)| n == name).map(|(_, v)| v);
+
+        if locked {
+            sync_locked(name, package, current, &lock, &options)
+                .await
+                .with_context(|| format!("Locked sync of [{}] failed", name))?;
+        } else if let Some(resolution) =
+            sync_unlocked(name, package, current, &lock, &options).await?
+        {
+            lock.packages.insert(name.clone(), resolution.into());
+            lock.save(&lock_path)?;
+        }
+    }
+
+    if prune {
+        for (name, _) in installed.iter() {
+            if !manifest.packages.contains_key(name) {
+                tracing::info!(target=%name, "removing package absent from manifest");
+                uninstall_target(name)?;
+            }
+        }
+    }
+
+    if !locked {
+        let before = lock.packages.len();
+        lock.packages
+            .retain(|name, _| manifest.packages.contains_key(name));
+        if lock.packages.len() != before {
+            lock.save(&lock_path)?;
+        }
+    }
+
+    tracing::info!("sync complete");
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use rood::sys::file;
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".");
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+/// Swaps a set of staged files into their final location.
+///
+/// Any file that would be overwritten, as well as every previous artifact of the package,
+/// is moved aside to a backup next to it. Dropping the swap without committing it
+/// removes the new files and restores the backups.
+#[derive(Debug, Default)]
+pub struct ArtifactSwap {
+    installed: Vec<PathBuf>,
+    backups: Vec<(PathBuf, PathBuf)>,
+    committed: bool,
+}
+
+impl ArtifactSwap {
+    #[tracing::instrument(skip(files, previous))]
+    pub fn apply(files: &[(PathBuf, PathBuf)], previous: &[PathBuf]) -> Result<ArtifactSwap> {
+        let mut swap = ArtifactSwap::default();
+
+        let displaced = previous.iter().chain(files.iter().map(|(_, dst)| dst));
+        for path in displaced {
+            if path.exists() && !swap.backups.iter().any(|(orig, _)| orig == path) {
+                let backup = sibling_path(path, "binman-bak");
+                fs::rename(path, &backup)
+                    .with_context(|| format!("failed to back up {}", path.display()))?;
+                tracing::debug!(path=?path, backup=?backup, "backed up artifact");
+                swap.backups.push((path.clone(), backup));
+            }
+        }
+
+        for (src, dst) in files.iter() {
+            let tmp = sibling_path(dst, "binman-new");
+            let placed = fs::copy(src, &tmp)
+                .and_then(|_| file::make_executable(&tmp))
+                .and_then(|_| fs::rename(&tmp, dst));
+
+            if let Err(e) = placed {
+                let _ = fs::remove_file(&tmp);
+                return Err(
+                    anyhow::Error::from(e).context(format!("failed to install {}", dst.display()))
+                );
+            }
+
+            tracing::debug!("produced asset {}", dst.display());
+            swap.installed.push(dst.clone());
+        }
+
+        Ok(swap)
+    }
+
+    /// The paths of the newly installed artifacts.
+    pub fn installed(&self) -> &[PathBuf] {
+        &self.installed
+    }
+
+    /// Makes the swap permanent by deleting the backups.
+    ///
+    /// Backups that cannot be deleted are left in place and reported, the new artifacts are kept.
+    pub fn commit(mut self) -> Result<()> {
+        let mut leftovers = Vec::new();
+        for (_, backup) in self.backups.iter() {
+            match fs::remove_file(backup) {
+                Ok(()) => tracing::debug!(backup=?backup, "removed backup"),
+                Err(e) => leftovers.push(format!("{}: {}", backup.display(), e)),
+            }
+        }
+        self.committed = true;
+
+        if !leftovers.is_empty() {
+            bail!("failed to remove backups:\n  {}", leftovers.join("\n  "));
+        }
+        Ok(())
+    }
+
+    /// Removes the new artifacts and restores every backup.
+    ///
+    /// Every step is attempted even if an earlier one failed, and the failures are reported together.
+    fn rollback(&mut self) -> Result<()> {
+        let mut failures = Vec::new();
+        for path in self.installed.drain(..) {
+            if path.exists() {
+                if let Err(e) = fs::remove_file(&path) {
+                    failures.push(format!("failed to remove {}: {}", path.display(), e));
+                }
+            }
+        }
+        for (orig, backup) in self.backups.drain(..) {
+            match fs::rename(&backup, &orig) {
+                Ok(()) => tracing::debug!(path=?orig, "restored artifact"),
+                Err(e) => failures.push(format!(
+                    "failed to restore {} from {}: {}",
+                    orig.display(),
+                    backup.display(),
+                    e
+                )),
+            }
+        }
+
+        if !failures.is_empty() {
+            bail!("rollback incomplete:\n  {}", failures.join("\n  "));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ArtifactSwap {
+    fn drop(&mut self) {
+        if self.committed || (self.installed.is_empty() && self.backups.is_empty()) {
+            return;
+        }
+        tracing::warn!("rolling back artifact changes");
+        if let Err(e) = self.rollback() {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    /// Creates a staging dir holding the new contents of each file name.
+    fn staged(files: &[(&str, &str)]) -> TempDir {
+        let dir = tempdir().unwrap();
+        for (name, contents) in files {
+            fs::write(dir.path().join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    fn swap_files(staging: &Path, dest: &Path, names: &[&str]) -> Vec<(PathBuf, PathBuf)> {
+        names
+            .iter()
+            .map(|name| (staging.join(name), dest.join(name)))
+            .collect()
+    }
+
+    fn read(path: PathBuf) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn commit_keeps_new_files_and_removes_backups() {
+        let staging = staged(&[("a", "new a")]);
+        let dest = tempdir().unwrap();
+        fs::write(dest.path().join("a"), "old a").unwrap();
+        fs::write(dest.path().join("old"), "old").unwrap();
+
+        let files = swap_files(staging.path(), dest.path(), &["a"]);
+        let swap = ArtifactSwap::apply(&files, &[dest.path().join("old")]).unwrap();
+        swap.commit().unwrap();
+
+        assert_eq!(read(dest.path().join("a")), "new a");
+        assert!(!dest.path().join("old").exists());
+        assert_eq!(fs::read_dir(dest.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn drop_rolls_back() {
+        let staging = staged(&[("a", "new a"), ("b", "new b")]);
+        let dest = tempdir().unwrap();
+        fs::write(dest.path().join("a"), "old a").unwrap();
+        fs::write(dest.path().join("old"), "old").unwrap();
+
+        let files = swap_files(staging.path(), dest.path(), &["a", "b"]);
+        let swap = ArtifactSwap::apply(&files, &[dest.path().join("old")]).unwrap();
+        assert_eq!(read(dest.path().join("b")), "new b");
+        drop(swap);
+
+        assert_eq!(read(dest.path().join("a")), "old a");
+        assert_eq!(read(dest.path().join("old")), "old");
+        assert!(!dest.path().join("b").exists());
+        assert_eq!(fs::read_dir(dest.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn rollback_continues_past_failures() {
+        let staging = staged(&[("a", "new a"), ("b", "new b"), ("c", "new c")]);
+        let dest = tempdir().unwrap();
+        for name in ["a", "b", "c"] {
+            fs::write(dest.path().join(name), format!("old {}", name)).unwrap();
+        }
+
+        let files = swap_files(staging.path(), dest.path(), &["a", "b", "c"]);
+        let mut swap = ArtifactSwap::apply(&files, &[]).unwrap();
+
+        // Neither can the new "a" be removed, nor the backup of "b" be restored.
+        fs::remove_file(dest.path().join("a")).unwrap();
+        fs::create_dir(dest.path().join("a")).unwrap();
+        fs::write(dest.path().join("a").join("file"), "").unwrap();
+        fs::remove_file(sibling_path(&dest.path().join("b"), "binman-bak")).unwrap();
+
+        let err = swap.rollback().unwrap_err().to_string();
+        assert!(err.contains("failed to remove"), "{}", err);
+        assert!(err.contains("failed to restore"), "{}", err);
+
+        assert_eq!(read(dest.path().join("c")), "old c");
+        assert!(!dest.path().join("b").exists());
+
+        // Nothing is left for the drop to roll back again.
+        drop(swap);
+        assert_eq!(read(dest.path().join("c")), "old c");
+    }
+}
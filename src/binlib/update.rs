@@ -1,52 +1,373 @@
-use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use crate::github::Client;
-use crate::{install::async_install, uninstall_target, Config, State, StateEntry};
+use anyhow::{anyhow, bail, Result};
 
-async fn async_update(
-    entry: &StateEntry,
-    install_location: &str,
+use futures::stream::{self, StreamExt};
+
+use semver::{Version, VersionReq};
+
+use crate::http::DownloadLimit;
+use crate::install::{
+    check_conflicts, configure, find_release, find_tagged_release, stage_release, InstallOptions,
+    Resolution, StagedRelease,
+};
+use crate::source::{self, Release, ReleaseSource, Repository};
+use crate::{Channel, Config, State, StateEntry, VersionSpec};
+
+/// An update of an installed package, with the version, channel and tag pattern to follow.
+struct UpdatePlan {
+    entry: StateEntry,
+    repo: Repository,
+    version: VersionSpec,
+    requirement: Option<VersionReq>,
+    channel: Channel,
+    options: InstallOptions,
+}
+
+impl UpdatePlan {
+    /// Settles what to update to, falling back on what was recorded at install time.
+    fn new(
+        entry: StateEntry,
+        version: Option<&str>,
+        options: &InstallOptions,
+    ) -> Result<UpdatePlan> {
+        let version = match version {
+            Some(raw) => VersionSpec::parse(raw)?,
+            None => entry
+                .requirement
+                .clone()
+                .map(VersionSpec::Requirement)
+                .unwrap_or(VersionSpec::Latest),
+        };
+        let requirement = match &version {
+            VersionSpec::Requirement(req) => Some(req.clone()),
+            VersionSpec::Latest => None,
+            VersionSpec::Exact(_) => entry.requirement.clone(),
+        };
+
+        let mut options = options.clone();
+        let channel = options
+            .channel
+            .clone()
+            .unwrap_or_else(|| entry.channel.clone());
+        options.channel = Some(channel.clone());
+        if options.tag_pattern.is_none() {
+            options.tag_pattern = entry.tag_pattern.clone();
+        }
+
+        Ok(UpdatePlan {
+            repo: Repository::from_url(&entry.url)?,
+            entry,
+            version,
+            requirement,
+            channel,
+            options,
+        })
+    }
+
+    /// Looks up the release to update to, and whether it differs from the installed one.
+    async fn check(&self, source: &dyn ReleaseSource) -> Result<(Release, bool)> {
+        let entry = &self.entry;
+        let tag_pattern = self.options.tag_pattern.as_ref();
+        let release = match &self.options.pinned {
+            Some(pin) => find_tagged_release(source, &self.repo, &pin.tag, tag_pattern).await?,
+            None => {
+                find_release(
+                    source,
+                    &self.repo,
+                    &self.version,
+                    &self.channel,
+                    tag_pattern,
+                )
+                .await?
+            }
+        };
+
+        let changed = match &self.channel {
+            // Nightly tags keep their version, only their contents change.
+            Channel::Nightly(_) => {
+                self.channel != entry.channel || release.revision() != entry.revision.as_deref()
+            }
+            _ => self.version.needs_change(&entry.version, release.version()),
+        };
+        Ok((release, changed))
+    }
+
+    /// Downloads the release to update to, if the package must change.
+    async fn stage(
+        &self,
+        source: &dyn ReleaseSource,
+        force: bool,
+    ) -> Result<Option<StagedRelease>> {
+        let entry = &self.entry;
+        let (release, changed) = self.check(source).await?;
+        let release_v = release.version();
+
+        if changed || force {
+            tracing::info!(target=%entry.name, version=%release_v, channel=%self.channel, "upgrade available");
+            Ok(Some(
+                stage_release(source, &entry.url, &release, &self.options).await?,
+            ))
+        } else {
+            tracing::info!(target=%entry.name, "nothing to do");
+            Ok(None)
+        }
+    }
+
+    /// Swaps a staged release in and records it, or records a change of channel or tag pattern.
+    fn apply(
+        self,
+        cfg: &Config,
+        state: &mut State,
+        staged: Option<StagedRelease>,
+    ) -> Result<Option<Resolution>> {
+        let entry = self.entry;
+        match staged {
+            Some(staged) => {
+                // The previous artifacts are only discarded once the new entry is committed to state.
+                let install_dir = install_dir(cfg, &entry);
+                check_conflicts(
+                    state,
+                    &entry.name,
+                    &staged.destinations(&install_dir),
+                    self.options.overwrite,
+                )?;
+                let swap = staged.swap_into(&install_dir, &entry.artifacts)?;
+                let mut new_entry = staged.entry(&entry.name, &swap)?;
+                new_entry.requirement = self.requirement;
+                new_entry.channel = self.channel;
+                new_entry.tag_pattern = self.options.tag_pattern;
+                state.replace(new_entry)?;
+                swap.commit()?;
+                tracing::info!(target=%entry.name, "update complete");
+                Ok(Some(staged.resolution().clone()))
+            }
+            None => {
+                if self.channel != entry.channel || self.options.tag_pattern != entry.tag_pattern {
+                    // Switching channels takes effect on the next update that finds a release.
+                    let mut new_entry = entry.clone();
+                    new_entry.channel = self.channel;
+                    new_entry.tag_pattern = self.options.tag_pattern;
+                    state.replace(new_entry)?;
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// The directory a package was installed to, which may differ from the configured one.
+fn install_dir(cfg: &Config, entry: &StateEntry) -> PathBuf {
+    entry
+        .artifacts
+        .iter()
+        .filter_map(|a| Path::new(a).parent())
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(&cfg.install_location))
+}
+
+/// Updates a package to its latest release, staying within the requirement it was installed with.
+#[tracing::instrument(skip(options))]
+pub async fn update_target(target: &str, force: bool, options: &InstallOptions) -> Result<()> {
+    update_target_to(target, None, force, options).await?;
+    Ok(())
+}
+
+/// Sources of the release lookups, shared by the packages of a host.
+type Sources = HashMap<String, Result<Box<dyn ReleaseSource>>>;
+
+/// The sources of the repositories of the planned updates.
+///
+/// A source that cannot be set up, e.g. because of a bad CA certificate, only fails the packages
+/// of its host.
+fn plan_sources(cfg: &Config, plans: &[UpdatePlan], offline: bool) -> Sources {
+    let mut sources = Sources::new();
+    for plan in plans.iter() {
+        if !sources.contains_key(&plan.repo.host) {
+            let source = source::for_repository(cfg, &plan.repo, offline);
+            sources.insert(plan.repo.host.clone(), source);
+        }
+    }
+    sources
+}
+
+/// The source of the repository of a planned update.
+fn plan_source<'a>(sources: &'a Sources, plan: &UpdatePlan) -> Result<&'a dyn ReleaseSource> {
+    match &sources[&plan.repo.host] {
+        Ok(source) => Ok(source.as_ref()),
+        Err(e) => Err(anyhow!("{:#}", e)),
+    }
+}
+
+/// Packages looked up at once, as many as downloads allowed at once.
+fn concurrency(options: &InstallOptions) -> usize {
+    options
+        .download_limit
+        .as_ref()
+        .map(DownloadLimit::max)
+        .unwrap_or(1)
+}
+
+/// Applies updates, looking up and downloading their releases concurrently.
+///
+/// Every package is attempted even when others fail.
+async fn update_plans(
+    cfg: &Config,
+    state: &mut State,
+    plans: Vec<UpdatePlan>,
     force: bool,
-) -> Result<Option<StateEntry>> {
-    let client = Client::new()?;
-    let repo = client.get_repository(&entry.url)?;
-    let latest = client.latest_release(&repo).await?;
-
-    let latest_v = latest.version();
-    if latest_v > entry.version || force {
-        tracing::info!(target=%entry.name, version=%latest_v, "upgrade available");
-        uninstall_target(&entry.name)?;
-        Ok(Some(
-            async_install(&entry.url, &latest_v.to_string(), install_location).await?,
-        ))
-    } else {
-        tracing::info!("nothing to do");
-        Ok(None)
-    }
-}
-
-#[tracing::instrument]
-pub async fn update_target(target: &str, force: bool) -> Result<()> {
-    let cfg = Config::new()?;
+    options: &InstallOptions,
+) -> Result<()> {
+    let sources = plan_sources(cfg, &plans, options.offline);
+    let staged = stream::iter(plans.iter())
+        .map(|plan| async { plan.stage(plan_source(&sources, plan)?, force).await })
+        .buffered(concurrency(options))
+        .collect::<Vec<_>>()
+        .await;
 
-    // Get existing entry.
-    let entry;
-    {
-        // Get read scope on state.
-        let state = State::new(&cfg.state_file_path)?;
-        entry = state
-            .get_copy(target)
-            .ok_or_else(|| anyhow!("Binary [{}] is not installed", target))?;
-    }
-
-    let possible_new_entry = async_update(&entry, &cfg.install_location, force).await?;
-    {
-        // Get write scope on state.
-        let mut state = State::new(&cfg.state_file_path)?;
-        if let Some(new_entry) = possible_new_entry {
-            state.insert(new_entry)?;
+    // Swaps and state changes are applied one package at a time.
+    let count = plans.len();
+    let mut failures = 0;
+    for (plan, staged) in plans.into_iter().zip(staged) {
+        let name = plan.entry.name.clone();
+        if let Err(e) = staged.and_then(|staged| plan.apply(cfg, state, staged)) {
+            tracing::error!(target=%name, "update failed: {:#}", e);
+            failures += 1;
         }
     }
 
+    if failures > 0 {
+        bail!("{} of {} package(s) failed to update", failures, count);
+    }
     Ok(())
 }
+
+/// Updates several packages, looking up and downloading their releases concurrently.
+#[tracing::instrument(skip(options))]
+pub async fn update_targets(
+    targets: &[String],
+    force: bool,
+    options: &InstallOptions,
+) -> Result<()> {
+    let cfg = Config::new()?;
+    let mut state = State::new(&cfg.state_file_path)?;
+    let options = configure(&cfg, options);
+
+    let plans = targets
+        .iter()
+        .map(|target| {
+            let entry = state
+                .get_copy(target)
+                .ok_or_else(|| anyhow!("Binary [{}] is not installed", target))?;
+            UpdatePlan::new(entry, None, &options)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    update_plans(&cfg, &mut state, plans, force, &options).await
+}
+
+/// Updates every installed package.
+#[tracing::instrument(skip(options))]
+pub async fn update_all(force: bool, options: &InstallOptions) -> Result<()> {
+    let cfg = Config::new()?;
+    let mut state = State::new(&cfg.state_file_path)?;
+    let options = configure(&cfg, options);
+
+    let mut entries = state.list().into_iter().cloned().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let plans = entries
+        .into_iter()
+        .map(|entry| UpdatePlan::new(entry, None, &options))
+        .collect::<Result<Vec<_>>>()?;
+
+    update_plans(&cfg, &mut state, plans, force, &options).await
+}
+
+/// The release an update of an installed package would move to.
+#[derive(Debug)]
+pub struct UpdateCheck {
+    pub name: String,
+    pub current: Version,
+    pub channel: Channel,
+
+    /// The version an update would install, if the lookup succeeded.
+    pub available: Option<Version>,
+
+    /// Whether an update would change the package.
+    pub outdated: bool,
+}
+
+/// Looks up the release each installed package would be updated to, without installing anything.
+///
+/// Packages whose lookup fails are reported without an available version.
+#[tracing::instrument(skip(options))]
+pub async fn check_updates(options: &InstallOptions) -> Result<Vec<UpdateCheck>> {
+    let cfg = Config::new()?;
+    let state = State::new(&cfg.state_file_path)?;
+    let options = configure(&cfg, options);
+
+    let mut entries = state.list().into_iter().cloned().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let plans = entries
+        .into_iter()
+        .map(|entry| UpdatePlan::new(entry, None, &options))
+        .collect::<Result<Vec<_>>>()?;
+
+    let sources = plan_sources(&cfg, &plans, options.offline);
+    let checks = stream::iter(plans.iter())
+        .map(|plan| async {
+            let found = match plan_source(&sources, plan) {
+                Ok(source) => plan.check(source).await,
+                Err(e) => Err(e),
+            };
+            let (available, outdated) = match found {
+                Ok((release, changed)) => (Some(release.version().clone()), changed),
+                Err(e) => {
+                    tracing::error!(target=%plan.entry.name, "lookup failed: {:#}", e);
+                    (None, false)
+                }
+            };
+            UpdateCheck {
+                name: plan.entry.name.clone(),
+                current: plan.entry.version.clone(),
+                channel: plan.channel.clone(),
+                available,
+                outdated,
+            }
+        })
+        .buffered(concurrency(&options))
+        .collect::<Vec<_>>()
+        .await;
+    Ok(checks)
+}
+
+/// Moves an installed package to the given version, `latest`, a specific version or a requirement.
+///
+/// Without a version, the requirement recorded at install time is used. A requirement replaces
+/// the recorded one, `latest` drops it and a specific version keeps it. The channel and tag
+/// pattern of the options, if any, replace the recorded ones.
+///
+/// Returns the release that was installed, if any.
+#[tracing::instrument(skip(options))]
+pub async fn update_target_to(
+    target: &str,
+    version: Option<&str>,
+    force: bool,
+    options: &InstallOptions,
+) -> Result<Option<Resolution>> {
+    let cfg = Config::new()?;
+    let mut state = State::new(&cfg.state_file_path)?;
+    let options = configure(&cfg, options);
+
+    // Get existing entry.
+    let entry = state
+        .get_copy(target)
+        .ok_or_else(|| anyhow!("Binary [{}] is not installed", target))?;
+
+    let plan = UpdatePlan::new(entry, version, &options)?;
+    let source = source::for_repository(&cfg, &plan.repo, options.offline)?;
+    let staged = plan.stage(source.as_ref(), force).await?;
+    plan.apply(&cfg, &mut state, staged)
+}
@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use super::checksum::sha256_file;
+use super::{Config, State};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArtifactStatus {
+    /// The artifact content differs from the one recorded at install time.
+    Modified,
+
+    /// The artifact no longer exists.
+    Missing,
+
+    /// The file sits in an install directory but belongs to no package.
+    Extra,
+
+    /// No digest was recorded for the artifact, so it cannot be verified.
+    Unrecorded,
+}
+
+impl ArtifactStatus {
+    /// Whether the status denotes a drift from the recorded state.
+    pub fn is_drift(&self) -> bool {
+        *self != ArtifactStatus::Unrecorded
+    }
+}
+
+#[derive(Debug)]
+pub struct Discrepancy {
+    /// The package owning the artifact, if any.
+    pub package: Option<String>,
+    pub path: String,
+    pub status: ArtifactStatus,
+}
+
+/// Rehashes every installed artifact and compares it to the digest recorded in state.
+///
+/// Files found in an install directory that belong to no package are reported as extra.
+pub fn verify_artifacts() -> Result<Vec<Discrepancy>> {
+    let cfg = Config::new()?;
+    let state = State::new(&cfg.state_file_path)?;
+
+    let mut discrepancies = Vec::new();
+    let mut known_artifacts = HashSet::new();
+    let mut install_dirs = HashSet::new();
+    install_dirs.insert(cfg.install_location.clone());
+
+    let mut entries = state.list();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for entry in entries.into_iter() {
+        for artifact in entry.artifacts.iter() {
+            known_artifacts.insert(artifact.clone());
+            if let Some(parent) = Path::new(artifact).parent().and_then(|p| p.to_str()) {
+                install_dirs.insert(String::from(parent));
+            }
+
+            let status = if !Path::new(artifact).exists() {
+                Some(ArtifactStatus::Missing)
+            } else if let Some(expected_hash) = entry.checksums.get(artifact) {
+                if sha256_file(Path::new(artifact))? != *expected_hash {
+                    Some(ArtifactStatus::Modified)
+                } else {
+                    None
+                }
+            } else {
+                Some(ArtifactStatus::Unrecorded)
+            };
+
+            tracing::debug!(artifact=%artifact, status=?status, "verified artifact");
+            if let Some(status) = status {
+                discrepancies.push(Discrepancy {
+                    package: Some(entry.name.clone()),
+                    path: artifact.clone(),
+                    status,
+                });
+            }
+        }
+    }
+
+    let mut install_dirs = install_dirs.into_iter().collect::<Vec<_>>();
+    install_dirs.sort();
+    for dir in install_dirs.iter() {
+        let dir_entries = match fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+
+        let mut extra = Vec::new();
+        for dir_entry in dir_entries.filter_map(|e| e.ok()) {
+            let path = dir_entry.path();
+            let path_str = String::from(path.to_str().unwrap_or_default());
+            if path.is_file() && !known_artifacts.contains(&path_str) {
+                extra.push(path_str);
+            }
+        }
+        extra.sort();
+
+        discrepancies.extend(extra.into_iter().map(|path| Discrepancy {
+            package: None,
+            path,
+            status: ArtifactStatus::Extra,
+        }));
+    }
+
+    Ok(discrepancies)
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+use semver::{Version, VersionReq};
+
+use crate::fuzzy_semver::parse_version_strict;
+
+/// The version of a package requested by the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VersionSpec {
+    /// The latest release.
+    Latest,
+
+    /// A specific release, e.g. `1.2.3` or `v1.2.3`.
+    Exact(Version),
+
+    /// The highest release matching a requirement, e.g. `^1.4` or `>=0.9, <1`.
+    Requirement(VersionReq),
+}
+
+impl VersionSpec {
+    /// Parses a version spec.
+    ///
+    /// Full versions are exact, as a bare `1.2.3` would otherwise be read as the requirement `^1.2.3`.
+    pub fn parse(raw: &str) -> Result<VersionSpec> {
+        if raw == "latest" {
+            return Ok(VersionSpec::Latest);
+        }
+        if let Ok(version) = parse_version_strict(raw) {
+            return Ok(VersionSpec::Exact(version));
+        }
+        VersionReq::parse(raw)
+            .map(VersionSpec::Requirement)
+            .map_err(|e| anyhow!("Invalid version or requirement \"{}\": {}", raw, e))
+    }
+
+    /// Whether a version satisfies the spec, ignoring whether it is the latest one.
+    ///
+    /// Prereleases only satisfy a requirement when included, in which case they are compared
+    /// as the version they precede.
+    pub fn matches(&self, version: &Version, include_prereleases: bool) -> bool {
+        match self {
+            VersionSpec::Latest => include_prereleases || version.pre.is_empty(),
+            VersionSpec::Exact(v) => v == version,
+            VersionSpec::Requirement(req) => {
+                req.matches(version)
+                    || (include_prereleases
+                        && !version.pre.is_empty()
+                        && req.matches(&Version::new(version.major, version.minor, version.patch)))
+            }
+        }
+    }
+
+    /// Whether an installed version must be replaced by a candidate release to satisfy the spec.
+    ///
+    /// Exact versions may require a downgrade, while other specs only move forward unless the
+    /// installed version falls outside of the requirement.
+    pub fn needs_change(&self, installed: &Version, candidate: &Version) -> bool {
+        match self {
+            VersionSpec::Latest => candidate > installed,
+            VersionSpec::Exact(_) => candidate != installed,
+            VersionSpec::Requirement(req) => candidate > installed || !req.matches(installed),
+        }
+    }
+}
+
+impl fmt::Display for VersionSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionSpec::Latest => write!(f, "latest"),
+            VersionSpec::Exact(v) => write!(f, "{}", v),
+            VersionSpec::Requirement(req) => write!(f, "{}", req),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(raw: &str) -> Version {
+        Version::parse(raw).unwrap()
+    }
+
+    fn req(raw: &str) -> VersionSpec {
+        VersionSpec::Requirement(VersionReq::parse(raw).unwrap())
+    }
+
+    #[test]
+    fn parses_specs() {
+        assert_eq!(VersionSpec::parse("latest").unwrap(), VersionSpec::Latest);
+        assert_eq!(
+            VersionSpec::parse("1.2.3").unwrap(),
+            VersionSpec::Exact(v("1.2.3"))
+        );
+        assert_eq!(
+            VersionSpec::parse("v1.2.3-rc.1").unwrap(),
+            VersionSpec::Exact(v("1.2.3-rc.1"))
+        );
+        assert_eq!(VersionSpec::parse("^1.4").unwrap(), req("^1.4"));
+        assert_eq!(VersionSpec::parse("1.4").unwrap(), req("^1.4"));
+        assert_eq!(VersionSpec::parse(">=0.9, <1").unwrap(), req(">=0.9, <1"));
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        for raw in ["", "newest", "1.2.3.4", ">=x", "^1.4 || ^2"] {
+            let err = VersionSpec::parse(raw).unwrap_err().to_string();
+            assert!(err.contains("Invalid version"), "{}: {}", raw, err);
+        }
+    }
+
+    #[test]
+    fn matches_exact_versions() {
+        let spec = VersionSpec::Exact(v("1.2.3"));
+        assert!(spec.matches(&v("1.2.3"), false));
+        assert!(!spec.matches(&v("1.2.4"), false));
+
+        assert!(!spec.needs_change(&v("1.2.3"), &v("1.2.3")));
+        assert!(spec.needs_change(&v("1.3.0"), &v("1.2.3")));
+        assert!(spec.needs_change(&v("1.0.0"), &v("1.2.3")));
+    }
+
+    #[test]
+    fn matches_latest() {
+        let spec = VersionSpec::Latest;
+        assert!(spec.matches(&v("2.0.0"), false));
+        assert!(!spec.matches(&v("2.0.0-rc.1"), false));
+        assert!(spec.matches(&v("2.0.0-rc.1"), true));
+
+        assert!(spec.needs_change(&v("1.0.0"), &v("1.1.0")));
+        assert!(!spec.needs_change(&v("1.1.0"), &v("1.1.0")));
+        // Latest never downgrades, e.g. when the newest release was pulled.
+        assert!(!spec.needs_change(&v("1.1.0"), &v("1.0.0")));
+    }
+
+    #[test]
+    fn matches_ranges() {
+        let spec = req(">=1.4, <2");
+        assert!(spec.matches(&v("1.4.0"), false));
+        assert!(spec.matches(&v("1.9.9"), false));
+        assert!(!spec.matches(&v("2.0.0"), false));
+        assert!(!spec.matches(&v("1.3.9"), false));
+
+        assert!(spec.needs_change(&v("1.4.0"), &v("1.5.0")));
+        assert!(!spec.needs_change(&v("1.5.0"), &v("1.4.0")));
+        // An installed version outside of the requirement is moved back within it.
+        assert!(spec.needs_change(&v("2.1.0"), &v("1.9.0")));
+    }
+
+    #[test]
+    fn matches_prereleases_against_ranges() {
+        let spec = req("^1.4");
+        assert!(!spec.matches(&v("1.5.0-rc.1"), false));
+        assert!(spec.matches(&v("1.5.0-rc.1"), true));
+        assert!(!spec.matches(&v("2.0.0-rc.1"), true));
+        assert!(!spec.matches(&v("1.4.0-rc.1"), false));
+    }
+}
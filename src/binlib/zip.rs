@@ -1,131 +1,522 @@
-use std::path::Path;
-use std::process::{Command, Stdio};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read};
+use std::path::{Component, Path, PathBuf};
 
-use anyhow::{ensure, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 
-#[derive(Clone, Copy, Debug)]
+use tar::EntryType;
+
+/// Offset and value of the magic string identifying a POSIX tar header.
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_MAGIC: &[u8] = b"ustar";
+
+/// File type bits of a unix mode, as stored in zip archives.
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CompressionType {
     Zip,
-    Tarball,
+    Tar,
+    Gzip,
+    Xz,
+    Bzip2,
     Zstd,
 }
 
-#[cfg(target_family = "unix")]
-fn unzip_unix(zip_file: &Path, tgt_dir: &Path) -> Result<()> {
-    // TODO: Validate that unzip is installed.
-    // This throws a bad error.
-    let mut child = Command::new("unzip")
-        .arg(zip_file.to_str().unwrap())
-        .arg("-d")
-        .arg(tgt_dir.to_str().unwrap())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-
-    let status = child.wait()?;
-    ensure!(
-        status.success(),
-        "Error with unzip - status: {}",
-        status.code().unwrap_or(1)
-    );
-    Ok(())
+impl CompressionType {
+    /// The canonical file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressionType::Zip => "zip",
+            CompressionType::Tar => "tar",
+            CompressionType::Gzip => "gz",
+            CompressionType::Xz => "xz",
+            CompressionType::Bzip2 => "bz2",
+            CompressionType::Zstd => "zst",
+        }
+    }
+
+    fn from_magic(header: &[u8]) -> Option<CompressionType> {
+        if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            Some(CompressionType::Zip)
+        } else if header.starts_with(&[0x1f, 0x8b]) {
+            Some(CompressionType::Gzip)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(CompressionType::Xz)
+        } else if header.starts_with(b"BZh") {
+            Some(CompressionType::Bzip2)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(CompressionType::Zstd)
+        } else if is_tar(header) {
+            Some(CompressionType::Tar)
+        } else {
+            None
+        }
+    }
 }
 
-fn unzip(zip_file: &Path, tgt_dir: &Path) -> Result<()> {
-    if cfg!(unix) {
-        unzip_unix(zip_file, tgt_dir)
-    } else {
-        // TODO: Implement other platforms.
-        unimplemented!();
+fn is_tar(header: &[u8]) -> bool {
+    header
+        .get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len())
+        .map(|magic| magic == TAR_MAGIC)
+        .unwrap_or(false)
+}
+
+/// Reads up to `len` bytes, stopping early only at the end of the stream.
+fn read_header<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(len);
+    reader.take(len as u64).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Checks that an entry path stays within the extraction directory.
+fn ensure_enclosed(path: &Path) -> Result<()> {
+    for component in path.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir => bail!(unsafe_entry(path, "path traversal")),
+            Component::RootDir | Component::Prefix(_) => bail!(unsafe_entry(path, "absolute path")),
+        }
     }
+    Ok(())
 }
 
-#[cfg(target_family = "unix")]
-fn untar_unix(tar_file: &Path, tgt_dir: &Path) -> Result<()> {
-    let mut child = Command::new("tar")
-        .arg("xvzf")
-        .arg(tar_file)
-        .arg("-C")
-        .arg(tgt_dir)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
+/// Depth of an entry's parent below the extraction directory, once earlier links are resolved.
+fn real_depth(tgt_dir: &Path, entry_path: &Path) -> Result<usize> {
+    let parent = entry_path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect::<PathBuf>();
 
-    let status = child.wait()?;
-    if status.success() {
-        Ok(())
-    } else {
-        let code = status.code().unwrap_or(1);
-        ensure!(status.success(), "Error with untar - status: {}", code);
-        Ok(())
+    let mut existing = tgt_dir.join(&parent);
+    let mut missing = 0;
+    while !existing.exists() && existing.pop() {
+        missing += 1;
     }
+
+    let real_parent = fs::canonicalize(&existing)?;
+    let depth = real_parent
+        .strip_prefix(fs::canonicalize(tgt_dir)?)
+        .map_err(|_| unsafe_entry(entry_path, "link parent escapes the destination"))?
+        .components()
+        .count();
+    Ok(depth + missing)
 }
 
-fn untar(tar_file: &Path, tgt_dir: &Path) -> Result<()> {
-    if cfg!(unix) {
-        untar_unix(tar_file, tgt_dir)
-    } else {
-        // TODO: Implement other platforms
-        unimplemented!()
+/// Checks that a symlink created at `entry_path` points within the extraction directory.
+///
+/// Parent components are only allowed before any normal component, so that they can be
+/// resolved against the real location of the link without following other links.
+fn ensure_safe_symlink(tgt_dir: &Path, entry_path: &Path, target: &Path) -> Result<()> {
+    let mut depth = real_depth(tgt_dir, entry_path)?;
+
+    let mut seen_normal = false;
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => seen_normal = true,
+            Component::CurDir => {}
+            Component::ParentDir if !seen_normal && depth > 0 => depth -= 1,
+            Component::ParentDir => {
+                bail!(unsafe_entry(entry_path, "symlink escapes the destination"))
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                bail!(unsafe_entry(entry_path, "symlink to an absolute path"))
+            }
+        }
     }
+    Ok(())
+}
+
+fn unsafe_entry(path: &Path, reason: &str) -> anyhow::Error {
+    anyhow!(
+        "Refusing to extract unsafe archive entry {:?}: {}",
+        path,
+        reason
+    )
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    // Links are only expected to point to other files of the archive.
+    fs::copy(link.parent().unwrap_or(Path::new("")).join(target), link).map(|_| ())
 }
 
-#[cfg(target_family = "unix")]
-fn unzstd_unix(zstd_file: &Path, tgt_dir: &Path) -> Result<()> {
-    std::fs::create_dir_all(tgt_dir)?;
+fn unzip(zip_file: &Path, tgt_dir: &Path) -> Result<()> {
+    let mut archive = ::zip::ZipArchive::new(BufReader::new(File::open(zip_file)?))?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let entry_path = PathBuf::from(entry.name());
+        ensure_enclosed(&entry_path)?;
+        let out_path = tgt_dir.join(&entry_path);
 
-    let no_ext = zstd_file.with_extension("");
-    let path = no_ext.file_name().unwrap();
+        let mode = entry.unix_mode().unwrap_or(0);
+        match mode & S_IFMT {
+            _ if entry.is_dir() => {
+                fs::create_dir_all(&out_path)?;
+                continue;
+            }
+            S_IFLNK => {
+                let mut target = String::new();
+                entry.read_to_string(&mut target)?;
+                ensure_safe_symlink(tgt_dir, &entry_path, Path::new(&target))?;
+                if let Some(parent) = out_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                create_symlink(Path::new(&target), &out_path)?;
+            }
+            0 | S_IFREG => {
+                if let Some(parent) = out_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                io::copy(&mut entry, &mut File::create(&out_path)?)?;
+                #[cfg(unix)]
+                if mode != 0 {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(&out_path, fs::Permissions::from_mode(mode & 0o777))?;
+                }
+            }
+            _ => bail!(unsafe_entry(&entry_path, "special file")),
+        }
+    }
+    Ok(())
+}
 
-    tracing::debug!("unzstd: path: {:?}", path);
+fn untar<R: Read>(reader: R, tgt_dir: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
 
-    let mut child = Command::new("unzstd")
-        .arg("-d")
-        .arg(zstd_file)
-        .arg("-o")
-        .arg(path)
-        .current_dir(tgt_dir)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        ensure_enclosed(&entry_path)?;
 
-    let status = child.wait()?;
-    if status.success() {
-        // zstd is single file, we must make it executable.
-        let tgt_path = tgt_dir.join(path);
-        rood::sys::file::make_executable(&tgt_path)?;
-        tracing::debug!("made executable: {:?}", tgt_path);
-        Ok(())
-    } else {
-        let code = status.code().unwrap_or(1);
-        // Print the stdout
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous | EntryType::Directory => {}
+            EntryType::Symlink => {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| unsafe_entry(&entry_path, "symlink without a target"))?;
+                ensure_safe_symlink(tgt_dir, &entry_path, &target)?;
+            }
+            EntryType::Link => {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| unsafe_entry(&entry_path, "hard link without a target"))?;
+                ensure_enclosed(&target)?;
+            }
+            EntryType::XGlobalHeader | EntryType::XHeader => continue,
+            _ => bail!(unsafe_entry(&entry_path, "special file")),
+        }
 
-        ensure!(status.success(), "Error with zstd - status: {}", code);
-        Ok(())
+        ensure!(
+            entry.unpack_in(tgt_dir)?,
+            unsafe_entry(&entry_path, "path traversal")
+        );
     }
+    Ok(())
 }
 
-fn unzstd(zstd_file: &Path, tgt_dir: &Path) -> Result<()> {
-    if cfg!(unix) {
-        unzstd_unix(zstd_file, tgt_dir)
-    } else {
-        unimplemented!()
+/// Inflates a compressed stream, which is either a tarball or a single file.
+fn decompress<R: Read>(mut decoder: R, file_name: PathBuf, tgt_dir: &Path) -> Result<()> {
+    let header = read_header(&mut decoder, TAR_MAGIC_OFFSET + TAR_MAGIC.len())?;
+    let is_tarball = is_tar(&header);
+    let mut stream = Cursor::new(header).chain(decoder);
+
+    if is_tarball {
+        tracing::debug!("compressed stream is a tarball");
+        return untar(stream, tgt_dir);
     }
+
+    // Single compressed files are expected to be executables.
+    let tgt_path = tgt_dir.join(file_name);
+    io::copy(&mut stream, &mut File::create(&tgt_path)?)?;
+    rood::sys::file::make_executable(&tgt_path)?;
+    tracing::debug!("made executable: {:?}", tgt_path);
+    Ok(())
 }
 
 pub fn extract(path: &Path, tgt_dir: &Path, compression: CompressionType) -> Result<()> {
+    fs::create_dir_all(tgt_dir)?;
+
+    // The name of the single file held by a compressed stream, if it is one.
+    let inner_name = PathBuf::from(path.with_extension("").file_name().unwrap());
+    let reader = BufReader::new(File::open(path)?);
+
     match compression {
         CompressionType::Zip => unzip(path, tgt_dir),
-        CompressionType::Tarball => untar(path, tgt_dir),
-        CompressionType::Zstd => unzstd(path, tgt_dir),
+        CompressionType::Tar => untar(reader, tgt_dir),
+        CompressionType::Gzip => {
+            decompress(flate2::read::GzDecoder::new(reader), inner_name, tgt_dir)
+        }
+        CompressionType::Xz => decompress(xz2::read::XzDecoder::new(reader), inner_name, tgt_dir),
+        CompressionType::Bzip2 => {
+            decompress(bzip2::read::BzDecoder::new(reader), inner_name, tgt_dir)
+        }
+        CompressionType::Zstd => decompress(zstd::Decoder::new(reader)?, inner_name, tgt_dir),
     }
+    .with_context(|| format!("failed to extract {:?} as {:?}", path, compression))
 }
 
+/// Detects the compression of a file from its leading bytes.
+pub fn detect_compression(path: &Path) -> Result<Option<CompressionType>> {
+    let mut file = File::open(path)?;
+    let header = read_header(&mut file, TAR_MAGIC_OFFSET + TAR_MAGIC.len())?;
+    Ok(CompressionType::from_magic(&header))
+}
+
+/// Guesses the compression of a file from its extension.
 pub fn get_compression(ext: &str) -> Option<CompressionType> {
     match ext {
         "zip" => Some(CompressionType::Zip),
-        "txz" | "tgz" | "gz" | "xz" => Some(CompressionType::Tarball),
-        "zst" => Some(CompressionType::Zstd),
+        "tar" => Some(CompressionType::Tar),
+        "tgz" | "gz" => Some(CompressionType::Gzip),
+        "txz" | "xz" => Some(CompressionType::Xz),
+        "tbz" | "tbz2" | "bz2" => Some(CompressionType::Bzip2),
+        "tzst" | "zst" => Some(CompressionType::Zstd),
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tar::{EntryType, Header};
+
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    /// Mode of a named pipe, which the zip writer cannot produce by itself.
+    const S_IFIFO: u32 = 0o010000;
+
+    /// Offset of the external attributes within a zip central directory header.
+    const CENTRAL_EXTERNAL_ATTRS_OFFSET: usize = 38;
+
+    /// Describes a tar entry, written without the path validation of the tar builder.
+    struct TarEntry<'a> {
+        path: &'a str,
+        kind: EntryType,
+        link: Option<&'a str>,
+        data: &'a [u8],
+    }
+
+    fn entry(path: &str, kind: EntryType) -> TarEntry<'_> {
+        TarEntry {
+            path,
+            kind,
+            link: None,
+            data: b"",
+        }
+    }
+
+    fn file<'a>(path: &'a str, data: &'a [u8]) -> TarEntry<'a> {
+        TarEntry {
+            data,
+            ..entry(path, EntryType::Regular)
+        }
+    }
+
+    fn link<'a>(path: &'a str, kind: EntryType, target: &'a str) -> TarEntry<'a> {
+        TarEntry {
+            link: Some(target),
+            ..entry(path, kind)
+        }
+    }
+
+    fn tarball(entries: &[TarEntry]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for e in entries {
+            let mut header = Header::new_old();
+            let old = header.as_old_mut();
+            old.name[..e.path.len()].copy_from_slice(e.path.as_bytes());
+            if let Some(target) = e.link {
+                old.linkname[..target.len()].copy_from_slice(target.as_bytes());
+            }
+            header.set_entry_type(e.kind);
+            header.set_mode(0o755);
+            header.set_size(e.data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, e.data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn untar_in_temp(entries: &[TarEntry]) -> (TempDir, Result<()>) {
+        let dir = tempdir().unwrap();
+        let result = untar(Cursor::new(tarball(entries)), dir.path());
+        (dir, result)
+    }
+
+    fn assert_refused(result: Result<()>, reason: &str) {
+        let message = format!("{:#}", result.expect_err("the archive must be refused"));
+        assert!(message.contains(reason), "unexpected error: {}", message);
+    }
+
+    #[test]
+    fn tar_refuses_path_traversal() {
+        let (dir, result) = untar_in_temp(&[file("../evil", b"x")]);
+        assert_refused(result, "path traversal");
+        assert!(!dir.path().parent().unwrap().join("evil").exists());
+    }
+
+    #[test]
+    fn tar_refuses_absolute_paths() {
+        let (_dir, result) = untar_in_temp(&[file("/tmp/binman-evil", b"x")]);
+        assert_refused(result, "absolute path");
+    }
+
+    #[test]
+    fn tar_refuses_special_files() {
+        for kind in [EntryType::Fifo, EntryType::Char, EntryType::Block] {
+            let (_dir, result) = untar_in_temp(&[entry("special", kind)]);
+            assert_refused(result, "special file");
+        }
+    }
+
+    #[test]
+    fn tar_refuses_absolute_symlinks() {
+        let (_dir, result) = untar_in_temp(&[link("l", EntryType::Symlink, "/etc/passwd")]);
+        assert_refused(result, "symlink to an absolute path");
+    }
+
+    #[test]
+    fn tar_refuses_chained_symlinks_escaping() {
+        let (_dir, result) = untar_in_temp(&[
+            entry("d/", EntryType::Directory),
+            link("d/l", EntryType::Symlink, ".."),
+            link("d/l/l2", EntryType::Symlink, ".."),
+        ]);
+        assert_refused(result, "symlink escapes the destination");
+    }
+
+    #[test]
+    fn tar_refuses_escaping_hard_links() {
+        let (_dir, result) = untar_in_temp(&[link("h", EntryType::Link, "../../etc/passwd")]);
+        assert_refused(result, "path traversal");
+
+        let (_dir, result) = untar_in_temp(&[link("h", EntryType::Link, "/etc/passwd")]);
+        assert_refused(result, "absolute path");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn tar_extracts_enclosed_symlinks() {
+        let (dir, result) = untar_in_temp(&[
+            entry("bin/", EntryType::Directory),
+            file("bin/tool", b"#!/bin/sh\n"),
+            link("tool", EntryType::Symlink, "bin/tool"),
+            link("bin/self", EntryType::Symlink, "../tool"),
+        ]);
+        result.unwrap();
+        assert_eq!(
+            fs::read_link(dir.path().join("tool")).unwrap(),
+            Path::new("bin/tool")
+        );
+        assert_eq!(fs::read(dir.path().join("tool")).unwrap(), b"#!/bin/sh\n");
+        assert!(dir.path().join("bin/self").exists());
+    }
+
+    /// A zip entry: a file with its contents, or a symlink with its target.
+    enum ZipEntry<'a> {
+        File(&'a str),
+        Symlink(&'a str, &'a str),
+    }
+
+    fn zip_in_temp(entries: &[ZipEntry], patch: impl Fn(&mut Vec<u8>)) -> (TempDir, Result<()>) {
+        let mut writer = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default().unix_permissions(0o755);
+        for e in entries {
+            match e {
+                ZipEntry::File(name) => {
+                    writer.start_file(*name, options).unwrap();
+                    writer.write_all(b"x").unwrap();
+                }
+                ZipEntry::Symlink(name, target) => {
+                    writer.add_symlink(*name, *target, options).unwrap();
+                }
+            }
+        }
+        let mut bytes = writer.finish().unwrap().into_inner();
+        patch(&mut bytes);
+
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("archive.zip");
+        fs::write(&archive, bytes).unwrap();
+        let out = dir.path().join("out");
+        fs::create_dir_all(&out).unwrap();
+        let result = unzip(&archive, &out);
+        (dir, result)
+    }
+
+    #[test]
+    fn zip_refuses_path_traversal() {
+        let (dir, result) = zip_in_temp(&[ZipEntry::File("../evil")], |_| {});
+        assert_refused(result, "path traversal");
+        assert!(!dir.path().join("evil").exists());
+    }
+
+    #[test]
+    fn zip_refuses_absolute_paths() {
+        let (_dir, result) = zip_in_temp(&[ZipEntry::File("/tmp/binman-evil")], |_| {});
+        assert_refused(result, "absolute path");
+    }
+
+    #[test]
+    fn zip_refuses_special_files() {
+        let (_dir, result) = zip_in_temp(&[ZipEntry::File("fifo")], |bytes| {
+            let central = bytes.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+            let attrs = central + CENTRAL_EXTERNAL_ATTRS_OFFSET;
+            bytes[attrs..attrs + 4].copy_from_slice(&((S_IFIFO | 0o755) << 16).to_le_bytes());
+        });
+        assert_refused(result, "special file");
+    }
+
+    #[test]
+    fn zip_refuses_absolute_symlinks() {
+        let (_dir, result) = zip_in_temp(&[ZipEntry::Symlink("l", "/etc/passwd")], |_| {});
+        assert_refused(result, "symlink to an absolute path");
+    }
+
+    #[test]
+    fn zip_refuses_chained_symlinks_escaping() {
+        let (_dir, result) = zip_in_temp(
+            &[
+                ZipEntry::File("d/f"),
+                ZipEntry::Symlink("d/l", ".."),
+                ZipEntry::Symlink("d/l/l2", ".."),
+            ],
+            |_| {},
+        );
+        assert_refused(result, "symlink escapes the destination");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn zip_extracts_enclosed_symlinks() {
+        let (dir, result) = zip_in_temp(
+            &[
+                ZipEntry::File("bin/tool"),
+                ZipEntry::Symlink("tool", "bin/tool"),
+            ],
+            |_| {},
+        );
+        result.unwrap();
+        let out = dir.path().join("out");
+        assert_eq!(
+            fs::read_link(out.join("tool")).unwrap(),
+            Path::new("bin/tool")
+        );
+        assert_eq!(fs::read(out.join("tool")).unwrap(), b"x");
+    }
+}
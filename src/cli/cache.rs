@@ -0,0 +1,74 @@
+use anyhow::Result;
+
+use binlib::{Cache, Config};
+
+use clap::Parser;
+
+use indicatif::{HumanBytes, HumanDuration};
+
+#[derive(Parser)]
+pub struct CacheCommand {
+    #[clap(subcommand)]
+    action: CacheAction,
+}
+
+#[derive(Parser)]
+enum CacheAction {
+    /// List cached downloads.
+    #[clap(name = "list")]
+    List,
+
+    /// Remove everything from the cache.
+    #[clap(name = "clean")]
+    Clean,
+
+    /// Remove cached downloads that were not used recently.
+    #[clap(name = "prune")]
+    Prune {
+        /// How long a download may go unused before it is removed, e.g. `30d`.
+        #[clap(long = "older-than", value_name = "DURATION")]
+        older_than: humantime::Duration,
+    },
+}
+
+impl CacheCommand {
+    pub async fn run(&self) -> Result<()> {
+        let cfg = Config::new()?;
+        let cache = Cache::new(&cfg.cache_dir);
+
+        match &self.action {
+            CacheAction::List => {
+                let mut entries = cache.entries()?;
+                entries.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+                for entry in entries.iter() {
+                    tracing::info!(
+                        "{} ({}, used {} ago) - {}",
+                        entry.file_name,
+                        HumanBytes(entry.size),
+                        HumanDuration(entry.age()),
+                        entry.url
+                    );
+                }
+                let total: u64 = entries.iter().map(|e| e.size).sum();
+                tracing::info!(
+                    "{} cached download(s), {}",
+                    entries.len(),
+                    HumanBytes(total)
+                );
+            }
+            CacheAction::Clean => {
+                cache.clean()?;
+                tracing::info!("cache cleaned");
+            }
+            CacheAction::Prune { older_than } => {
+                let summary = cache.prune(**older_than)?;
+                tracing::info!(
+                    "removed {} download(s), freeing {}",
+                    summary.entries,
+                    HumanBytes(summary.bytes)
+                );
+            }
+        }
+        Ok(())
+    }
+}
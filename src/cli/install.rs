@@ -1,6 +1,7 @@
 use anyhow::Result;
 
-use binlib::install_target;
+use binlib::fuzzy_semver::TagPattern;
+use binlib::{install_target, Channel, InstallOptions, ProgressHandler};
 
 use clap::Parser;
 
@@ -10,18 +11,74 @@ pub struct InstallCommand {
     #[clap(name = "repo_url")]
     repo_url: String,
 
-    /// The pacakge version.
+    /// The package version: `latest`, a specific version or a requirement such as `^1.4`.
     #[clap(name = "version", default_value = "latest")]
     version: String,
 
     /// The installation directory (overrides config.json)
     #[clap(name = "dir", long = "dir", value_name = "INSTALL_DIR")]
     dir: Option<String>,
+
+    /// Whether to overwrite files owned by another package, or by no package.
+    #[clap(short = 'f', long = "force", visible_alias = "overwrite")]
+    force: bool,
+
+    /// Whether to refuse installing assets for which the release publishes no checksum.
+    #[clap(long = "require-checksum")]
+    require_checksum: bool,
+
+    /// Whether to install from the download cache only, without network access.
+    #[clap(long = "offline")]
+    offline: bool,
+
+    /// Whether to follow prereleases, shorthand for `--channel prerelease`.
+    #[clap(long = "pre", conflicts_with = "channel")]
+    pre: bool,
+
+    /// The release channel to follow: `stable`, `prerelease` or `nightly:<tag>`.
+    #[clap(long = "channel", value_name = "CHANNEL")]
+    channel: Option<Channel>,
+
+    /// A regex locating the version in release tags, within a `version` or first capture group.
+    ///
+    /// Releases whose tag does not match are ignored, now and on updates.
+    #[clap(long = "tag-pattern", value_name = "REGEX")]
+    tag_pattern: Option<TagPattern>,
+
+    /// Only consider releases tagged with this prefix followed by a version, e.g. `cli-v`.
+    #[clap(
+        long = "tag-prefix",
+        value_name = "PREFIX",
+        conflicts_with = "tag-pattern"
+    )]
+    tag_prefix: Option<String>,
+
+    /// The name to install the package under (defaults to the repository name)
+    #[clap(long = "name", value_name = "NAME")]
+    name: Option<String>,
 }
 
 impl InstallCommand {
-    pub async fn run(&self) -> Result<()> {
-        install_target(&self.repo_url, &self.version, self.dir.as_ref()).await?;
+    pub async fn run(&self, progress: Option<ProgressHandler>) -> Result<()> {
+        let options = InstallOptions {
+            require_checksum: self.require_checksum,
+            offline: self.offline,
+            channel: if self.pre {
+                Some(Channel::Prerelease)
+            } else {
+                self.channel.clone()
+            },
+            tag_pattern: self
+                .tag_prefix
+                .as_deref()
+                .map(TagPattern::from_prefix)
+                .or_else(|| self.tag_pattern.clone()),
+            name: self.name.clone(),
+            overwrite: self.force,
+            progress,
+            ..Default::default()
+        };
+        install_target(&self.repo_url, &self.version, self.dir.as_ref(), &options).await?;
         Ok(())
     }
 }
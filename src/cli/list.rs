@@ -18,7 +18,20 @@ impl ListCommand {
         installed_applications.sort_by(|a, b| a.name.cmp(&b.name));
 
         for entry in installed_applications.iter() {
-            tracing::info!("{}@{}", &entry.name, &entry.version);
+            let mut qualifiers = Vec::new();
+            if let Some(req) = &entry.requirement {
+                qualifiers.push(req.to_string());
+            }
+            if !entry.channel.is_stable() {
+                qualifiers.push(entry.channel.to_string());
+            }
+
+            if qualifiers.is_empty() {
+                tracing::info!("{}@{}", &entry.name, &entry.version);
+            } else {
+                let qualifiers = qualifiers.join(", ");
+                tracing::info!("{}@{} ({})", &entry.name, &entry.version, qualifiers);
+            }
         }
         Ok(())
     }
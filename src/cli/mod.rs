@@ -1,9 +1,19 @@
+mod cache;
 mod install;
 mod list;
+mod outdated;
+mod progress;
+mod sync;
 mod uninstall;
 mod update;
+mod verify;
 
+pub use cache::CacheCommand;
 pub use install::InstallCommand;
 pub use list::ListCommand;
+pub use outdated::OutdatedCommand;
+pub use progress::progress_handler;
+pub use sync::SyncCommand;
 pub use uninstall::UninstallCommand;
 pub use update::UpdateCommand;
+pub use verify::VerifyCommand;
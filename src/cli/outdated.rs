@@ -0,0 +1,58 @@
+use anyhow::{ensure, Result};
+
+use binlib::{check_updates, InstallOptions};
+
+use clap::Parser;
+
+#[derive(Parser)]
+pub struct OutdatedCommand {
+    /// Whether to check against the cached release listings only, without network access.
+    #[clap(long = "offline")]
+    offline: bool,
+}
+
+impl OutdatedCommand {
+    pub async fn run(&self) -> Result<()> {
+        let options = InstallOptions {
+            offline: self.offline,
+            ..Default::default()
+        };
+        let checks = check_updates(&options).await?;
+
+        for check in checks.iter() {
+            let channel = if check.channel.is_stable() {
+                String::new()
+            } else {
+                format!(" ({})", check.channel)
+            };
+            match &check.available {
+                Some(available) if check.outdated => tracing::warn!(
+                    "{}@{} -> {}{}",
+                    &check.name,
+                    &check.current,
+                    available,
+                    channel
+                ),
+                Some(_) => tracing::info!(
+                    "{}@{} is up to date{}",
+                    &check.name,
+                    &check.current,
+                    channel
+                ),
+                None => tracing::warn!("{}@{} could not be checked", &check.name, &check.current),
+            }
+        }
+
+        let unchecked = checks.iter().filter(|c| c.available.is_none()).count();
+        let outdated = checks.iter().filter(|c| c.outdated).count();
+        ensure!(
+            unchecked == 0,
+            "{} package(s) could not be checked",
+            unchecked
+        );
+        ensure!(outdated == 0, "{} package(s) are outdated", outdated);
+
+        tracing::info!("all packages are up to date");
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use binlib::{DownloadEvent, ProgressHandler};
+
+use indicatif::{HumanBytes, HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
+
+/// Interval between progress lines when the output is not interactive.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The handler displaying download progress: bars on a terminal, periodic lines otherwise.
+pub fn progress_handler(quiet: bool) -> Option<ProgressHandler> {
+    if quiet {
+        None
+    } else if io::stderr().is_terminal() {
+        Some(progress_bars())
+    } else {
+        Some(progress_lines())
+    }
+}
+
+fn progress_bars() -> ProgressHandler {
+    let known_size = ProgressStyle::with_template(
+        "{msg:40!} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}",
+    )
+    .unwrap()
+    .progress_chars("=> ");
+    let unknown_size =
+        ProgressStyle::with_template("{spinner} {msg:40!} {bytes} {bytes_per_sec}").unwrap();
+
+    let multi = MultiProgress::new();
+    // Bars are flagged once they received their first position, which may be a resumed offset.
+    let bars: Mutex<HashMap<String, (ProgressBar, bool)>> = Mutex::default();
+
+    ProgressHandler::new(move |event| {
+        let mut bars = bars.lock().unwrap();
+        match event {
+            DownloadEvent::Started { asset } => {
+                let bar = multi.add(
+                    ProgressBar::no_length()
+                        .with_style(unknown_size.clone())
+                        .with_message(asset.clone()),
+                );
+                bars.insert(asset.clone(), (bar, false));
+            }
+            DownloadEvent::Progress {
+                asset,
+                downloaded,
+                total,
+            } => {
+                if let Some((bar, positioned)) = bars.get_mut(asset) {
+                    if let (Some(total), None) = (total, bar.length()) {
+                        bar.set_length(*total);
+                        bar.set_style(known_size.clone());
+                    }
+                    bar.set_position(*downloaded);
+                    if !*positioned {
+                        // Resumed bytes must not count towards the rate.
+                        bar.reset_eta();
+                        *positioned = true;
+                    }
+                }
+            }
+            DownloadEvent::Finished { asset } => {
+                if let Some((bar, _)) = bars.remove(asset) {
+                    bar.finish_and_clear();
+                }
+            }
+        }
+    })
+}
+
+struct Transfer {
+    started: Instant,
+    last_report: Instant,
+    initial: Option<u64>,
+    downloaded: u64,
+}
+
+impl Transfer {
+    fn rate(&self) -> u64 {
+        let received = self.downloaded - self.initial.unwrap_or(0).min(self.downloaded);
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            (received as f64 / elapsed) as u64
+        } else {
+            0
+        }
+    }
+}
+
+fn progress_lines() -> ProgressHandler {
+    let transfers: Mutex<HashMap<String, Transfer>> = Mutex::default();
+
+    ProgressHandler::new(move |event| {
+        let mut transfers = transfers.lock().unwrap();
+        match event {
+            DownloadEvent::Started { asset } => {
+                tracing::info!(asset=%asset, "downloading");
+                let now = Instant::now();
+                transfers.insert(
+                    asset.clone(),
+                    Transfer {
+                        started: now,
+                        last_report: now,
+                        initial: None,
+                        downloaded: 0,
+                    },
+                );
+            }
+            DownloadEvent::Progress {
+                asset,
+                downloaded,
+                total,
+            } => {
+                let transfer = match transfers.get_mut(asset) {
+                    Some(transfer) => transfer,
+                    None => return,
+                };
+                transfer.initial.get_or_insert(*downloaded);
+                transfer.downloaded = *downloaded;
+                if transfer.last_report.elapsed() < PLAIN_INTERVAL {
+                    return;
+                }
+                transfer.last_report = Instant::now();
+
+                let rate = transfer.rate();
+                match total {
+                    Some(total) if rate > 0 => {
+                        let eta = Duration::from_secs(total.saturating_sub(*downloaded) / rate);
+                        tracing::info!(
+                            "{}: {}/{} at {}/s, ETA {}",
+                            asset,
+                            HumanBytes(*downloaded),
+                            HumanBytes(*total),
+                            HumanBytes(rate),
+                            HumanDuration(eta)
+                        );
+                    }
+                    _ => tracing::info!(
+                        "{}: {} at {}/s",
+                        asset,
+                        HumanBytes(*downloaded),
+                        HumanBytes(rate)
+                    ),
+                }
+            }
+            DownloadEvent::Finished { asset } => {
+                if let Some(transfer) = transfers.remove(asset) {
+                    tracing::info!(
+                        "{}: {} in {}",
+                        asset,
+                        HumanBytes(transfer.downloaded),
+                        HumanDuration(transfer.started.elapsed())
+                    );
+                }
+            }
+        }
+    })
+}
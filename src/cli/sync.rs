@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use binlib::{sync_manifest, InstallOptions, ProgressHandler};
+
+use clap::Parser;
+
+#[derive(Parser)]
+pub struct SyncCommand {
+    /// Path to the manifest.
+    #[clap(
+        name = "manifest",
+        long = "manifest",
+        short = 'm',
+        default_value = "binman.toml"
+    )]
+    manifest: PathBuf,
+
+    /// Whether to uninstall packages that are not listed in the manifest.
+    #[clap(long = "prune")]
+    prune: bool,
+
+    /// Whether to install the exact releases recorded in the lockfile, refusing any other tag, asset URL or digest.
+    #[clap(long = "locked")]
+    locked: bool,
+
+    /// Whether to overwrite files owned by another package, or by no package.
+    #[clap(short = 'f', long = "force", visible_alias = "overwrite")]
+    force: bool,
+
+    /// Whether to refuse installing assets for which the release publishes no checksum.
+    #[clap(long = "require-checksum")]
+    require_checksum: bool,
+
+    /// Whether to install from the download cache only, without network access.
+    #[clap(long = "offline")]
+    offline: bool,
+}
+
+impl SyncCommand {
+    pub async fn run(&self, progress: Option<ProgressHandler>) -> Result<()> {
+        let options = InstallOptions {
+            require_checksum: self.require_checksum,
+            offline: self.offline,
+            overwrite: self.force,
+            progress,
+            ..Default::default()
+        };
+        sync_manifest(&self.manifest, self.prune, self.locked, &options).await
+    }
+}
@@ -1,25 +1,71 @@
 use anyhow::Result;
 
-use binlib::update_target;
+use binlib::{update_all, update_targets, Channel, DownloadLimit, InstallOptions, ProgressHandler};
 
 use clap::Parser;
 
 #[derive(Parser)]
 pub struct UpdateCommand {
     /// The name of the package(s) to update.
+    #[clap(required_unless_present = "all", conflicts_with = "all")]
     binary: Vec<String>,
 
+    /// Whether to update every installed package.
+    #[clap(short = 'a', long = "all")]
+    all: bool,
+
     /// Whether to force a re-install if versions are identitcal.
     #[clap(short = 'f', long = "force")]
     force: bool,
+
+    /// Whether to overwrite files owned by another package, or by no package.
+    #[clap(long = "overwrite")]
+    overwrite: bool,
+
+    /// Whether to refuse installing assets for which the release publishes no checksum.
+    #[clap(long = "require-checksum")]
+    require_checksum: bool,
+
+    /// Whether to install from the download cache only, without network access.
+    #[clap(long = "offline")]
+    offline: bool,
+
+    /// Whether to follow prereleases, shorthand for `--channel prerelease`.
+    ///
+    /// The channel is recorded for the named packages, so it cannot be combined with `--all`.
+    #[clap(long = "pre", conflicts_with_all = &["channel", "all"])]
+    pre: bool,
+
+    /// The release channel to follow: `stable`, `prerelease` or `nightly:<tag>`.
+    ///
+    /// The channel is recorded for the named packages, so it cannot be combined with `--all`.
+    #[clap(long = "channel", value_name = "CHANNEL", conflicts_with = "all")]
+    channel: Option<Channel>,
+
+    /// The most downloads to run at once, defaulting to `max_concurrent_downloads`.
+    #[clap(short = 'j', long = "jobs", value_name = "N")]
+    jobs: Option<usize>,
 }
 
 impl UpdateCommand {
-    pub async fn run(&self) -> Result<()> {
-        for target in self.binary.iter() {
-            update_target(target, self.force).await?;
+    pub async fn run(&self, progress: Option<ProgressHandler>) -> Result<()> {
+        let options = InstallOptions {
+            require_checksum: self.require_checksum,
+            offline: self.offline,
+            overwrite: self.overwrite,
+            channel: if self.pre {
+                Some(Channel::Prerelease)
+            } else {
+                self.channel.clone()
+            },
+            progress,
+            download_limit: self.jobs.map(DownloadLimit::new),
+            ..Default::default()
+        };
+        if self.all {
+            update_all(self.force, &options).await
+        } else {
+            update_targets(&self.binary, self.force, &options).await
         }
-
-        Ok(())
     }
 }
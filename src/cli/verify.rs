@@ -0,0 +1,33 @@
+use anyhow::{ensure, Result};
+
+use binlib::verify_artifacts;
+
+use clap::Parser;
+
+#[derive(Parser)]
+pub struct VerifyCommand {}
+
+impl VerifyCommand {
+    pub async fn run(&self) -> Result<()> {
+        let discrepancies = verify_artifacts()?;
+
+        for discrepancy in discrepancies.iter() {
+            let package = discrepancy.package.as_deref().unwrap_or("-");
+            if discrepancy.status.is_drift() {
+                tracing::warn!(package=%package, status=?discrepancy.status, "{}", &discrepancy.path);
+            } else {
+                tracing::info!(package=%package, status=?discrepancy.status, "{}", &discrepancy.path);
+            }
+        }
+
+        let drift_count = discrepancies.iter().filter(|d| d.status.is_drift()).count();
+        ensure!(
+            drift_count == 0,
+            "{} file(s) drifted from state",
+            drift_count
+        );
+
+        tracing::info!("all artifacts verified");
+        Ok(())
+    }
+}
@@ -4,7 +4,10 @@ use anyhow::Result;
 
 use clap::Parser;
 
-use cli::{InstallCommand, ListCommand, UninstallCommand, UpdateCommand};
+use cli::{
+    progress_handler, CacheCommand, InstallCommand, ListCommand, OutdatedCommand, SyncCommand,
+    UninstallCommand, UpdateCommand, VerifyCommand,
+};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -15,6 +18,10 @@ struct Root {
     #[clap(short = 'v', long = "verbose", global = true)]
     verbose: bool,
 
+    /// Whether to hide download progress.
+    #[clap(short = 'q', long = "quiet", global = true)]
+    quiet: bool,
+
     #[clap(subcommand)]
     command: Domain,
 }
@@ -23,9 +30,13 @@ impl Root {
     pub async fn run(&self) -> Result<()> {
         match &self.command {
             Domain::List(cmd) => cmd.run().await?,
-            Domain::Install(cmd) => cmd.run().await?,
-            Domain::Update(cmd) => cmd.run().await?,
+            Domain::Install(cmd) => cmd.run(progress_handler(self.quiet)).await?,
+            Domain::Update(cmd) => cmd.run(progress_handler(self.quiet)).await?,
+            Domain::Outdated(cmd) => cmd.run().await?,
             Domain::Uninstall(cmd) => cmd.run().await?,
+            Domain::Verify(cmd) => cmd.run().await?,
+            Domain::Sync(cmd) => cmd.run(progress_handler(self.quiet)).await?,
+            Domain::Cache(cmd) => cmd.run().await?,
         };
 
         Ok(())
@@ -46,9 +57,25 @@ enum Domain {
     #[clap(name = "update")]
     Update(UpdateCommand),
 
+    /// Report the installed packages for which an update is available.
+    #[clap(name = "outdated")]
+    Outdated(OutdatedCommand),
+
     /// Uninstall a package.
     #[clap(name = "uninstall")]
     Uninstall(UninstallCommand),
+
+    /// Verify installed artifacts against the checksums recorded at install time.
+    #[clap(name = "verify")]
+    Verify(VerifyCommand),
+
+    /// Install, update or remove packages to match a manifest.
+    #[clap(name = "sync")]
+    Sync(SyncCommand),
+
+    /// Inspect or clean the download cache.
+    #[clap(name = "cache")]
+    Cache(CacheCommand),
 }
 
 #[tokio::main]